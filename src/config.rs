@@ -0,0 +1,366 @@
+use crate::dom;
+use crate::error::Error;
+use crate::filter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+
+/// Refresh interval in seconds if not configured.
+const REFRESH_INTERVAL: u32 = 60;
+/// Shortest refresh interval in seconds, to stay clear of API rate limits.
+pub const MIN_REFRESH_INTERVAL: u32 = 10;
+/// Number of pipelines displayed per project if not configured.
+const MAX_PIPELINES: usize = 5;
+/// Upper bound for the number of pipelines displayed per project.
+const MAX_PIPELINES_LIMIT: usize = 20;
+/// Number of requests in flight per source if not configured.
+const MAX_REQUESTS: usize = 4;
+/// Upper bound for the number of requests in flight per source.
+const MAX_REQUESTS_LIMIT: usize = 16;
+/// Number of lines shown of a failed job's log if not configured.
+const LOG_LINES: usize = 30;
+/// Upper bound for the number of lines shown of a job's log.
+const LOG_LINES_LIMIT: usize = 500;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    GitLab,
+    GitHub,
+    Jenkins,
+    Gitea,
+}
+
+/// API used to talk to GitLab.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GitLabApi {
+    /// One request per project, pipeline and job list.
+    #[default]
+    Rest,
+    /// Projects with their pipelines and jobs in a single paginated query.
+    GraphQl,
+}
+
+/// What is displayed per project.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum View {
+    /// The latest pipelines matching the ref filter.
+    #[default]
+    Pipelines,
+    /// The head pipelines of open merge requests, GitLab REST API only.
+    MergeRequests,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SourceConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// GitLab only.
+    #[serde(default)]
+    pub api: GitLabApi,
+    #[serde(default)]
+    pub view: View,
+    /// Offer buttons to retry, cancel and play jobs, GitLab REST API only.
+    /// Needs a token with `api` scope.
+    #[serde(default)]
+    pub actions: bool,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub server: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    pub token: String,
+    /// Maximum number of pages requested from paginated list endpoints.
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    #[serde(default)]
+    pub filter: filter::ProjectFilter,
+    /// Pipeline refs to show, falls back to the global setting.
+    #[serde(default)]
+    pub refs: Option<filter::RefFilter>,
+    /// Pipeline refs to show per project, keyed by project id or `group/name`.
+    #[serde(default)]
+    pub project_refs: HashMap<String, filter::RefFilter>,
+    /// Refresh interval in seconds, falls back to the global setting.
+    #[serde(default)]
+    pub refresh_interval: Option<u32>,
+    /// Number of pipelines displayed per project, falls back to the global setting.
+    #[serde(default)]
+    pub max_pipelines: Option<usize>,
+    /// Number of requests in flight during a refresh, falls back to the global setting.
+    #[serde(default)]
+    pub max_requests: Option<usize>,
+}
+
+fn default_max_pages() -> u32 {
+    10
+}
+
+impl SourceConfig {
+    /// Label displayed on the project cards, defaults to the server URL.
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.server)
+    }
+
+    pub fn refs_for(&self, project: &dom::Project) -> filter::RefFilter {
+        let path = format!("{}/{}", project.group, project.name);
+        self.project_refs
+            .get(&project.id)
+            .or_else(|| self.project_refs.get(&path))
+            .or(self.refs.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Reject `tags` and `merge_requests` where the provider cannot pass them on
+    /// to the server, as pipelines are not filtered by them on the client.
+    fn validate_refs(&self) -> Result<(), Error> {
+        let gitlab = self.provider == ProviderKind::GitLab;
+        let github = self.provider == ProviderKind::GitHub;
+        // GraphQL passes the refs of the source only, refs per project filter its result
+        let graphql = gitlab && self.api == GitLabApi::GraphQl;
+        let filters = self
+            .refs
+            .iter()
+            .map(|refs| (refs, true))
+            .chain(self.project_refs.values().map(|refs| (refs, !graphql)));
+        for (refs, passed_on) in filters {
+            if refs.tags && !(gitlab && passed_on) {
+                return Err(Error::Config(format!(
+                    "{}: refs `tags` needs GitLab, not per project with GraphQL",
+                    self.label()
+                )));
+            }
+            if refs.merge_requests && !((gitlab || github) && passed_on) {
+                return Err(Error::Config(format!(
+                    "{}: refs `merge_requests` needs GitLab/GitHub, not per project with GraphQL",
+                    self.label()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Config {
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+    /// Server of the single GitLab source of configs written before `sources`.
+    #[serde(default, skip_serializing)]
+    server: Option<String>,
+    /// Token of the single GitLab source of configs written before `sources`.
+    #[serde(default, skip_serializing)]
+    token: Option<String>,
+    /// Pipeline refs to show for all sources.
+    #[serde(default)]
+    pub refs: Option<filter::RefFilter>,
+    /// Refresh interval in seconds.
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u32,
+    /// Number of pipelines displayed per project.
+    #[serde(default = "default_max_pipelines")]
+    pub max_pipelines: usize,
+    /// Number of requests in flight per source during a refresh.
+    #[serde(default = "default_max_requests")]
+    pub max_requests: usize,
+    /// Number of lines shown of a failed job's log.
+    #[serde(default = "default_log_lines")]
+    pub log_lines: usize,
+}
+
+/// Refresh settings of a source with the global fallbacks applied.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Seconds between refresh cycles.
+    pub refresh_interval: u32,
+    /// Number of pipelines displayed per project.
+    pub max_pipelines: usize,
+    /// Number of requests in flight during a refresh.
+    pub max_requests: usize,
+}
+
+fn default_refresh_interval() -> u32 {
+    REFRESH_INTERVAL
+}
+
+fn default_max_pipelines() -> usize {
+    MAX_PIPELINES
+}
+
+fn default_max_requests() -> usize {
+    MAX_REQUESTS
+}
+
+fn default_log_lines() -> usize {
+    LOG_LINES
+}
+
+impl Config {
+    /// Parse the config passed from javascript and bring all values into valid ranges.
+    pub fn parse(config: &JsValue) -> Result<Self, Error> {
+        #[allow(deprecated)]
+        let mut config: Config = config
+            .into_serde()
+            .map_err(|e| Error::Config(e.to_string()))?;
+        if let (Some(server), Some(token)) = (config.server.take(), config.token.take()) {
+            console::log_1(&JsValue::from(
+                "Top level `server` and `token` are deprecated, move them into `sources`",
+            ));
+            let source = serde_json::json!({ "server": server, "token": token });
+            config
+                .sources
+                .push(serde_json::from_value(source).map_err(|e| Error::Config(e.to_string()))?);
+        }
+        if config.sources.is_empty() {
+            return Err(Error::Config(
+                "no sources, expected a `sources` list of servers with their token".into(),
+            ));
+        }
+
+        config.refresh_interval = valid_refresh_interval(config.refresh_interval);
+        config.max_pipelines = valid_max_pipelines(config.max_pipelines);
+        config.max_requests = valid_max_requests(config.max_requests);
+        config.log_lines = valid_log_lines(config.log_lines);
+        for source in config.sources.iter_mut() {
+            source.refresh_interval = source.refresh_interval.map(valid_refresh_interval);
+            source.max_pipelines = source.max_pipelines.map(valid_max_pipelines);
+            source.max_requests = source.max_requests.map(valid_max_requests);
+            source.filter.compile()?;
+            let rest = source.provider == ProviderKind::GitLab && source.api == GitLabApi::Rest;
+            if source.view == View::MergeRequests && !rest {
+                return Err(Error::Config(format!(
+                    "{}: the merge request view needs the GitLab REST API",
+                    source.label()
+                )));
+            }
+            if source.actions && !rest {
+                return Err(Error::Config(format!(
+                    "{}: job actions need the GitLab REST API",
+                    source.label()
+                )));
+            }
+            if source.refs.is_none() {
+                source.refs = config.refs.clone();
+            }
+            source.validate_refs()?;
+        }
+        Ok(config)
+    }
+
+    /// Effective refresh interval of a source in seconds.
+    pub fn refresh_interval(&self, source: &SourceConfig) -> u32 {
+        source.refresh_interval.unwrap_or(self.refresh_interval)
+    }
+
+    /// Effective refresh settings of a source.
+    pub fn limits(&self, source: &SourceConfig) -> Limits {
+        Limits {
+            refresh_interval: self.refresh_interval(source),
+            max_pipelines: source.max_pipelines.unwrap_or(self.max_pipelines),
+            max_requests: source.max_requests.unwrap_or(self.max_requests),
+        }
+    }
+}
+
+fn valid_refresh_interval(seconds: u32) -> u32 {
+    if seconds < MIN_REFRESH_INTERVAL {
+        console::log_1(&JsValue::from(format!(
+            "Refresh interval {}s too short, using {}s",
+            seconds, MIN_REFRESH_INTERVAL
+        )));
+        return MIN_REFRESH_INTERVAL;
+    }
+    seconds
+}
+
+fn valid_max_pipelines(count: usize) -> usize {
+    let valid = count.clamp(1, MAX_PIPELINES_LIMIT);
+    if valid != count {
+        console::log_1(&JsValue::from(format!(
+            "Number of pipelines {} out of range, using {}",
+            count, valid
+        )));
+    }
+    valid
+}
+
+fn valid_max_requests(count: usize) -> usize {
+    let valid = count.clamp(1, MAX_REQUESTS_LIMIT);
+    if valid != count {
+        console::log_1(&JsValue::from(format!(
+            "Number of requests {} out of range, using {}",
+            count, valid
+        )));
+    }
+    valid
+}
+
+fn valid_log_lines(count: usize) -> usize {
+    let valid = count.clamp(1, LOG_LINES_LIMIT);
+    if valid != count {
+        console::log_1(&JsValue::from(format!(
+            "Number of log lines {} out of range, using {}",
+            count, valid
+        )));
+    }
+    valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(config: serde_json::Value) -> SourceConfig {
+        let mut source = serde_json::json!({ "server": "https://ci.example.com", "token": "t" });
+        source
+            .as_object_mut()
+            .unwrap()
+            .extend(config.as_object().unwrap().clone());
+        serde_json::from_value(source).unwrap()
+    }
+
+    #[test]
+    fn tags_need_gitlab() {
+        let refs = serde_json::json!({ "tags": true });
+        assert!(source(serde_json::json!({ "refs": refs }))
+            .validate_refs()
+            .is_ok());
+        for provider in ["github", "jenkins", "gitea"] {
+            let source = source(serde_json::json!({ "provider": provider, "refs": refs }));
+            assert!(matches!(source.validate_refs(), Err(Error::Config(_))));
+        }
+    }
+
+    #[test]
+    fn merge_requests_need_gitlab_or_github() {
+        let refs = serde_json::json!({ "merge_requests": true });
+        for provider in ["gitlab", "github"] {
+            let source = source(serde_json::json!({ "provider": provider, "refs": refs }));
+            assert!(source.validate_refs().is_ok());
+        }
+        let source = source(serde_json::json!({
+            "provider": "jenkins",
+            "project_refs": { "ops/deploy": refs }
+        }));
+        assert!(source.validate_refs().is_err());
+    }
+
+    #[test]
+    fn graphql_passes_on_source_refs_only() {
+        let refs = serde_json::json!({ "tags": true });
+        let graphql = source(serde_json::json!({ "api": "graphql", "refs": refs }));
+        assert!(graphql.validate_refs().is_ok());
+        let graphql = source(serde_json::json!({
+            "api": "graphql",
+            "project_refs": { "ops/deploy": refs }
+        }));
+        assert!(graphql.validate_refs().is_err());
+        let rest = source(serde_json::json!({ "project_refs": { "ops/deploy": refs } }));
+        assert!(rest.validate_refs().is_ok());
+    }
+}
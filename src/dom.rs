@@ -1,3 +1,9 @@
+use crate::error::Error;
+use std::collections::HashSet;
+use web_sys::Element;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     CREATED,
     PENDING,
@@ -9,34 +15,180 @@ pub enum Status {
     MANUAL,
 }
 
+impl Status {
+    /// The pipeline or job will not change anymore, unless it is retried.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            Status::SUCCESS | Status::FAILED | Status::CANCELED | Status::SKIPPED
+        )
+    }
+}
+
+#[derive(Clone)]
 pub struct Job {
     pub name: String,
+    /// Stage of the job, empty if the provider has no stages.
+    pub stage: String,
     pub status: Status,
     pub link: String,
+    /// Pipeline triggered by this job, if it is a bridge.
+    pub downstream: Option<Downstream>,
+    /// Provider specific path of the job's log, offered for failed jobs.
+    pub log: Option<String>,
+    /// Actions offered as buttons, only if enabled for the source.
+    pub actions: Vec<Action>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    Retry,
+    Cancel,
+    Play,
 }
 
+impl ActionKind {
+    /// Name used in the button's `data-action` attribute and the API endpoint.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionKind::Retry => "retry",
+            ActionKind::Cancel => "cancel",
+            ActionKind::Play => "play",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "retry" => Some(ActionKind::Retry),
+            "cancel" => Some(ActionKind::Cancel),
+            "play" => Some(ActionKind::Play),
+            _ => None,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ActionKind::Retry => "Retry",
+            ActionKind::Cancel => "Cancel",
+            ActionKind::Play => "Run",
+        }
+    }
+
+    fn icon(&self) -> &'static str {
+        match self {
+            ActionKind::Retry => "fa-redo",
+            ActionKind::Cancel => "fa-ban",
+            ActionKind::Play => "fa-play-circle",
+        }
+    }
+
+    /// Status displayed while the action is in flight.
+    fn expected_status(&self) -> Status {
+        match self {
+            ActionKind::Retry | ActionKind::Play => Status::PENDING,
+            ActionKind::Cancel => Status::CANCELED,
+        }
+    }
+}
+
+/// An action on a job or pipeline, e.g. retrying a failed job.
+#[derive(Clone)]
+pub struct Action {
+    pub kind: ActionKind,
+    /// Provider specific path of the job or pipeline, e.g. `projects/42/jobs/7`.
+    pub target: String,
+}
+
+/// A child or multi-project pipeline triggered by a bridge job.
+#[derive(Clone)]
+pub struct Downstream {
+    pub id: i64,
+    pub status: Status,
+    pub r#ref: String,
+    pub link: String,
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Clone)]
 pub struct Pipeline {
-    pub id: i32,
+    pub id: i64,
     pub status: Status,
+    pub r#ref: String,
+    /// The merge request this is the head pipeline of, in the merge request view.
+    pub merge_request: Option<MergeRequest>,
+}
+
+#[derive(Clone)]
+pub struct MergeRequest {
+    pub iid: i64,
+    pub title: String,
+    pub author: String,
+    pub target_branch: String,
+    pub draft: bool,
+    pub link: String,
 }
 
+#[derive(Clone)]
 pub struct PipelineDetail {
-    pub id: i32,
+    pub id: i64,
     pub status: Status,
     pub r#ref: String,
     pub duration: i32,
+    pub actions: Vec<Action>,
+    /// Summary of the pipeline's test reports, if it has any.
+    pub tests: Option<TestSummary>,
+}
+
+#[derive(Clone)]
+pub struct TestSummary {
+    pub total: u32,
+    pub failed: u32,
+    pub skipped: u32,
+    pub error: u32,
+    /// Names of failed test cases, only the first few of a failed pipeline.
+    pub failing: Vec<String>,
+}
+
+/// Outcome of the latest requests of a source, shown in the status area.
+pub struct SourceStatus {
+    pub label: String,
+    /// Time of the last successful refresh, in milliseconds since the epoch.
+    pub last_success: Option<f64>,
+    pub last_error: Option<Error>,
+    /// Start of the refresh cycle in flight, in milliseconds since the epoch.
+    pub refreshing_since: Option<f64>,
+    /// End of a pause imposed by the server's rate limit.
+    pub throttled_until: Option<f64>,
+}
+
+impl SourceStatus {
+    pub fn new(label: &str) -> Self {
+        SourceStatus {
+            label: label.to_string(),
+            last_success: None,
+            last_error: None,
+            refreshing_since: None,
+            throttled_until: None,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Project {
-    pub id: i32,
+    pub id: String,
     pub name: String,
     pub group: String,
+    pub default_branch: Option<String>,
 }
 
 pub struct Dom {}
 
 impl Dom {
+    /// Element id of a project, unique across all configured sources.
+    pub fn project_key(source: usize, project_id: &str) -> String {
+        format!("s{}_pr{}", source, project_id)
+    }
+
     fn map_status_to_bg(status: &Status) -> &'static str {
         match status {
             Status::SUCCESS => "bg-success",
@@ -47,97 +199,375 @@ impl Dom {
         }
     }
 
+    /// Remove the projects of a source which are no longer in `projects`.
+    pub fn remove_stale_projects(
+        document: &web_sys::Document,
+        source: usize,
+        projects: &[Project],
+    ) {
+        let content = match document.get_element_by_id("Content") {
+            Some(content) => content,
+            None => return,
+        };
+        let prefix = Dom::project_key(source, "");
+        let keep: HashSet<String> = projects
+            .iter()
+            .map(|p| Dom::project_key(source, &p.id))
+            .collect();
+        Dom::remove_children(&content, |element| {
+            let id = element.id();
+            Dom::has_class(element, "project") && id.starts_with(&prefix) && !keep.contains(&id)
+        });
+    }
+
+    /// Remove a project, e.g. if it has no more pipelines to show.
+    pub fn remove_project(document: &web_sys::Document, project_key: &str) {
+        if let Some(project_container) = document.get_element_by_id(project_key) {
+            project_container.remove();
+        }
+    }
+
+    /// Remove the pipelines of a project which are no longer in `pipelines`.
+    pub fn remove_stale_pipelines(
+        document: &web_sys::Document,
+        project_key: &str,
+        pipelines: &[Pipeline],
+    ) {
+        let project_container = match document.get_element_by_id(project_key) {
+            Some(project_container) => project_container,
+            None => return,
+        };
+        let keep: HashSet<String> = pipelines
+            .iter()
+            .map(|p| format!("{}_pl{}", project_key, p.id))
+            .collect();
+        Dom::remove_children(&project_container, |element| {
+            Dom::has_class(element, "pipeline") && !keep.contains(&element.id())
+        });
+    }
+
+    /// Flag project cards whose data could not be refreshed, either a single
+    /// project or, without `project_key`, all projects of the source.
+    /// The flag is cleared when the project is updated again.
+    pub fn mark_stale(document: &web_sys::Document, source: usize, project_key: Option<&str>) {
+        let content = match document.get_element_by_id("Content") {
+            Some(content) => content,
+            None => return,
+        };
+        let prefix = Dom::project_key(source, "");
+        let children = content.children();
+        for element in (0..children.length()).filter_map(|i| children.item(i)) {
+            let id = element.id();
+            let affected = match project_key {
+                Some(project_key) => id == project_key,
+                None => id.starts_with(&prefix),
+            };
+            if affected && Dom::has_class(&element, "project") && !Dom::has_class(&element, "stale")
+            {
+                element.set_class_name(&format!("{} stale", element.class_name()));
+            }
+        }
+    }
+
+    /// Render the status line of a source: last successful refresh and last error.
+    pub fn update_source_status(
+        document: &web_sys::Document,
+        source: usize,
+        status: &SourceStatus,
+    ) -> Result<(), Error> {
+        let status_area = match document.get_element_by_id("Status") {
+            Some(status_area) => status_area,
+            None => {
+                let content = document
+                    .get_element_by_id("Content")
+                    .ok_or_else(|| Error::Dom("document should have content region".into()))?;
+                let status_area = Dom::create_element(document, "div")?;
+                status_area.set_class_name("status");
+                status_area.set_id("Status");
+                content
+                    .before_with_node_1(&status_area)
+                    .map_err(Error::dom)?;
+                status_area
+            }
+        };
+
+        let element_id = format!("status_s{}", source);
+        let source_container = match document.get_element_by_id(&element_id) {
+            Some(source_container) => source_container,
+            None => {
+                let source_container = Dom::create_element(document, "div")?;
+                source_container.set_id(&element_id);
+                Dom::append(&status_area, &source_container)?;
+                source_container
+            }
+        };
+
+        let class = match (&status.last_error, status.last_success) {
+            (Some(_), _) => "source-error",
+            (None, Some(_)) => "source-ok",
+            (None, None) => "source-pending",
+        };
+        source_container.set_class_name(&format!("source-status {}", class));
+        source_container.set_text_content(None);
+
+        let label = Dom::create_element(document, "span")?;
+        label.set_class_name("source-label");
+        label.set_text_content(Some(&status.label));
+        Dom::append(&source_container, &label)?;
+
+        let refreshed = Dom::create_element(document, "span")?;
+        refreshed.set_class_name("refreshed");
+        let icon = match status.refreshing_since {
+            Some(_) => "fa-sync fa-spin",
+            None => "fa-sync",
+        };
+        Dom::append(&refreshed, &Dom::create_icon(document, icon)?)?;
+        let time = match status.last_success {
+            Some(millis) => Dom::format_time(millis),
+            None => "never".to_string(),
+        };
+        refreshed.append_with_str_1(&time).map_err(Error::dom)?;
+        Dom::append(&source_container, &refreshed)?;
+
+        if let Some(until) = status.throttled_until {
+            let throttled = Dom::create_element(document, "span")?;
+            throttled.set_class_name("throttled");
+            Dom::append(
+                &throttled,
+                &Dom::create_icon(document, "fa-hourglass-half")?,
+            )?;
+            throttled
+                .append_with_str_1(&format!("paused until {}", Dom::format_time(until)))
+                .map_err(Error::dom)?;
+            Dom::append(&source_container, &throttled)?;
+        }
+
+        if let Some(error) = &status.last_error {
+            let message = Dom::create_element(document, "span")?;
+            message.set_class_name("error");
+            Dom::append(
+                &message,
+                &Dom::create_icon(document, "fa-exclamation-triangle")?,
+            )?;
+            message
+                .append_with_str_1(&error.to_string())
+                .map_err(Error::dom)?;
+            Dom::append(&source_container, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Local time of day, `HH:MM:SS`.
+    fn format_time(millis: f64) -> String {
+        let date = js_sys::Date::new(&millis.into());
+        format!(
+            "{:02}:{:02}:{:02}",
+            date.get_hours(),
+            date.get_minutes(),
+            date.get_seconds()
+        )
+    }
+
+    fn remove_children(parent: &Element, stale: impl Fn(&Element) -> bool) {
+        let children = parent.children();
+        // collect first, the collection is live
+        let stale: Vec<Element> = (0..children.length())
+            .filter_map(|i| children.item(i))
+            .filter(|element| stale(element))
+            .collect();
+        for element in stale {
+            element.remove();
+        }
+    }
+
+    fn create_element(document: &web_sys::Document, tag: &str) -> Result<Element, Error> {
+        document.create_element(tag).map_err(Error::dom)
+    }
+
+    fn append(parent: &Element, child: &Element) -> Result<(), Error> {
+        parent.append_child(child).map(|_| ()).map_err(Error::dom)
+    }
+
+    fn create_icon(document: &web_sys::Document, icon: &str) -> Result<Element, Error> {
+        let element = Dom::create_element(document, "i")?;
+        element.set_class_name(&format!("fas {}", icon));
+        Ok(element)
+    }
+
+    /// Only http(s) links are rendered, anything else (e.g. `javascript:`) from the API is dropped.
+    fn safe_link(link: &str) -> Option<&str> {
+        let lower = link.trim_start().to_ascii_lowercase();
+        if lower.starts_with("https://") || lower.starts_with("http://") {
+            Some(link)
+        } else {
+            None
+        }
+    }
+
+    /// Anchor opening `link` in a new tab, without target if the link is not safe.
+    fn create_link(document: &web_sys::Document, link: &str) -> Result<Element, Error> {
+        let anchor = Dom::create_element(document, "a")?;
+        if let Some(href) = Dom::safe_link(link) {
+            anchor.set_attribute("href", href).map_err(Error::dom)?;
+            anchor
+                .set_attribute("target", "_blank")
+                .map_err(Error::dom)?;
+            anchor
+                .set_attribute("rel", "noopener noreferrer")
+                .map_err(Error::dom)?;
+        }
+        Ok(anchor)
+    }
+
+    fn has_class(element: &Element, class: &str) -> bool {
+        element.class_name().split_whitespace().any(|c| c == class)
+    }
+
     pub fn update_project(
         document: &web_sys::Document,
-        id: i32,
+        project_key: &str,
+        label: &str,
         name: &str,
         group: &str,
-        pipelines: &Vec<Pipeline>,
-    ) {
-        let element_id = format!("pr{}", id);
-        let project_container = match document.get_element_by_id(&element_id) {
+        pipelines: &[Pipeline],
+    ) -> Result<(), Error> {
+        let project_container = match document.get_element_by_id(project_key) {
             Some(project_container) => project_container,
             None => {
                 let content = document
                     .get_element_by_id("Content")
-                    .expect("document should have content region");
-                let project_container = document
-                    .create_element("div")
-                    .expect("Failed to create project container");
+                    .ok_or_else(|| Error::Dom("document should have content region".into()))?;
+                let project_container = Dom::create_element(document, "div")?;
                 project_container.set_class_name("project hidden");
-                project_container.set_id(&element_id);
-                let project_name = document
-                    .create_element("h1")
-                    .expect("Failed to create project name");
+                project_container.set_id(project_key);
+                let project_name = Dom::create_element(document, "h1")?;
                 project_name.set_text_content(Some(&format!("{}/{}", group, name)));
-                project_container
-                    .append_child(&project_name)
-                    .expect("Failed to add project name");
+                Dom::append(&project_container, &project_name)?;
+                let source_label = Dom::create_element(document, "div")?;
+                source_label.set_class_name("source");
+                source_label.set_text_content(Some(label));
+                Dom::append(&project_container, &source_label)?;
 
-                content
-                    .append_child(&project_container)
-                    .expect("Failed to add project");
+                Dom::append(&content, &project_container)?;
                 project_container
             }
         };
 
-        if pipelines.len() > 0 {
+        if let Some(pipeline) = pipelines.first() {
             project_container.set_class_name(&format!(
                 "project {}",
-                Dom::map_status_to_bg(&pipelines[0].status)
+                Dom::map_status_to_bg(&pipeline.status)
             ));
         }
+        Ok(())
     }
 
-    pub fn update_pipeline(document: &web_sys::Document, project_id: i32, pipeline: &Pipeline) {
-        let element_id = format!("pr{}", project_id);
-        let project_container = document
-            .get_element_by_id(&element_id)
-            .expect("Failed to find project for pipeline");
+    /// Add or reorder the pipeline in its project, returns whether it was added.
+    pub fn update_pipeline(
+        document: &web_sys::Document,
+        project_key: &str,
+        pipeline: &Pipeline,
+    ) -> Result<bool, Error> {
+        let project_container = match document.get_element_by_id(project_key) {
+            Some(project_container) => project_container,
+            None => return Ok(false),
+        };
+
+        let element_id = format!("{}_pl{}", project_key, pipeline.id);
+        let (pipeline_container, added) = match document.get_element_by_id(&element_id) {
+            Some(pipeline_container) => {
+                // re-append to keep the order of the latest pipeline list
+                Dom::append(&project_container, &pipeline_container)?;
+                (pipeline_container, false)
+            }
+            None => {
+                let pipeline_container = Dom::create_element(document, "div")?;
+                pipeline_container.set_class_name("pipeline bg-skipped");
+                pipeline_container.set_id(&element_id);
 
-        let element_id = format!("pr{}_pl{}", project_id, pipeline.id);
-        if let None = document.get_element_by_id(&element_id) {
-            let pipeline_container = document
-                .create_element("div")
-                .expect("Failed to create pipeline container");
-            pipeline_container.set_class_name("pipeline bg-skipped");
-            pipeline_container.set_id(&element_id);
+                let label_container = Dom::create_element(document, "div")?;
+                label_container.set_class_name("label");
+                label_container.set_id(&format!("{}_pl{}_label", project_key, pipeline.id));
+                Dom::append(&pipeline_container, &label_container)?;
 
-            let label_container = document
-                .create_element("div")
-                .expect("Failed to create pipeline label container");
-            label_container.set_class_name("label");
-            label_container.set_id(&format!("pr{}_pl{}_label", project_id, pipeline.id));
+                let time_container = Dom::create_element(document, "div")?;
+                time_container.set_class_name("time");
+                time_container.set_id(&format!("{}_pl{}_time", project_key, pipeline.id));
+                Dom::append(&pipeline_container, &time_container)?;
 
-            pipeline_container
-                .append_child(&label_container)
-                .expect("Failed to add time element");
+                let tests_container = Dom::create_element(document, "div")?;
+                tests_container.set_class_name("tests");
+                tests_container.set_id(&format!("{}_pl{}_tests", project_key, pipeline.id));
+                Dom::append(&pipeline_container, &tests_container)?;
 
-            let time_container = document
-                .create_element("div")
-                .expect("Failed to create time container");
-            time_container.set_class_name("time");
-            time_container.set_id(&format!("pr{}_pl{}_time", project_id, pipeline.id));
+                Dom::append(&project_container, &pipeline_container)?;
+                (pipeline_container, true)
+            }
+        };
 
-            pipeline_container
-                .append_child(&time_container)
-                .expect("Failed to add time element");
+        if let Some(merge_request) = &pipeline.merge_request {
+            Dom::update_merge_request(document, &pipeline_container, &element_id, merge_request)?;
+        }
+        Ok(added)
+    }
 
-            project_container
-                .append_child(&pipeline_container)
-                .expect("Failed to add pipeline");
+    /// Title, author, target branch and draft flag above the head pipeline of a merge request.
+    fn update_merge_request(
+        document: &web_sys::Document,
+        pipeline_container: &Element,
+        pipeline_key: &str,
+        merge_request: &MergeRequest,
+    ) -> Result<(), Error> {
+        let element_id = format!("{}_mr", pipeline_key);
+        let merge_request_container = match document.get_element_by_id(&element_id) {
+            Some(merge_request_container) => merge_request_container,
+            None => {
+                let merge_request_container = Dom::create_element(document, "div")?;
+                merge_request_container.set_class_name("merge-request");
+                merge_request_container.set_id(&element_id);
+                pipeline_container
+                    .prepend_with_node_1(&merge_request_container)
+                    .map_err(Error::dom)?;
+                merge_request_container
+            }
         };
+        merge_request_container.set_text_content(None);
+
+        let title = Dom::create_link(document, &merge_request.link)?;
+        title.set_class_name("title");
+        title.set_text_content(Some(&format!(
+            "!{} {}",
+            merge_request.iid, merge_request.title
+        )));
+        Dom::append(&merge_request_container, &title)?;
+
+        let details = Dom::create_element(document, "div")?;
+        details.set_class_name("details");
+        if merge_request.draft {
+            let draft = Dom::create_element(document, "span")?;
+            draft.set_class_name("draft");
+            draft.set_text_content(Some("Draft"));
+            Dom::append(&details, &draft)?;
+        }
+        details
+            .append_with_str_1(&format!(
+                "{} \u{2192} {}",
+                merge_request.author, merge_request.target_branch
+            ))
+            .map_err(Error::dom)?;
+        Dom::append(&merge_request_container, &details)?;
+        Ok(())
     }
 
     pub fn update_pipeline_detail(
         document: &web_sys::Document,
-        project_id: i32,
+        project_key: &str,
         pipeline: &PipelineDetail,
-    ) {
-        let element_id = format!("pr{}_pl{}", project_id, pipeline.id);
+    ) -> Result<(), Error> {
+        let element_id = format!("{}_pl{}", project_key, pipeline.id);
         let pipeline_container = match document.get_element_by_id(&element_id) {
             Some(pipeline_container) => pipeline_container,
-            None => return,
+            None => return Ok(()),
         };
 
         pipeline_container.set_class_name(&format!(
@@ -149,97 +579,389 @@ impl Dom {
         let minutes: i32 = (pipeline.duration % 3600) / 60;
         let seconds = pipeline.duration % 60;
 
-        let element_id = format!("pr{}_pl{}_label", project_id, pipeline.id);
-        let label_container = document
-            .get_element_by_id(&element_id)
-            .expect("Failed to find label element");
-        label_container.set_text_content(Some(&format!(
-            "#{} / {}",
-            pipeline.id.to_string(),
-            pipeline.r#ref
-        )));
+        let element_id = format!("{}_pl{}_label", project_key, pipeline.id);
+        if let Some(label_container) = document.get_element_by_id(&element_id) {
+            let label = format!("#{} / {}", pipeline.id, pipeline.r#ref);
+            label_container.set_text_content(Some(&label));
+            for action in &pipeline.actions {
+                let title = format!("{} pipeline {}", action.kind.title(), label);
+                Dom::append(
+                    &label_container,
+                    &Dom::create_action_button(document, action, &title)?,
+                )?;
+            }
+        }
 
-        let element_id = format!("pr{}_pl{}_time", project_id, pipeline.id);
-        let time_container = document
-            .get_element_by_id(&element_id)
-            .expect("Failed to find time element");
-        time_container.set_inner_html(&format!(
-            r#"<i class="fas fa-clock"></i>{:02}:{:02}:{:02}"#,
-            hours, minutes, seconds
-        ));
+        let element_id = format!("{}_pl{}_time", project_key, pipeline.id);
+        if let Some(time_container) = document.get_element_by_id(&element_id) {
+            time_container.set_text_content(None);
+            Dom::append(&time_container, &Dom::create_icon(document, "fa-clock")?)?;
+            time_container
+                .append_with_str_1(&format!("{:02}:{:02}:{:02}", hours, minutes, seconds))
+                .map_err(Error::dom)?;
+        }
+
+        let element_id = format!("{}_pl{}_tests", project_key, pipeline.id);
+        if let Some(tests_container) = document.get_element_by_id(&element_id) {
+            tests_container.set_text_content(None);
+            tests_container.set_class_name("tests");
+            if let Some(tests) = &pipeline.tests {
+                Dom::append_test_summary(document, &tests_container, tests)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A badge with the test counts, followed by the names of failed tests.
+    fn append_test_summary(
+        document: &web_sys::Document,
+        tests_container: &Element,
+        tests: &TestSummary,
+    ) -> Result<(), Error> {
+        let failed = tests.failed + tests.error;
+        tests_container.set_class_name(if failed > 0 {
+            "tests tests-failed"
+        } else {
+            "tests tests-ok"
+        });
+
+        let badge = Dom::create_element(document, "span")?;
+        badge.set_class_name("badge");
+        Dom::append(&badge, &Dom::create_icon(document, "fa-vial")?)?;
+        let passed = tests.total.saturating_sub(failed + tests.skipped);
+        let mut counts = vec![format!("{}/{} passed", passed, tests.total)];
+        if tests.failed > 0 {
+            counts.push(format!("{} failed", tests.failed));
+        }
+        if tests.error > 0 {
+            counts.push(format!("{} errors", tests.error));
+        }
+        if tests.skipped > 0 {
+            counts.push(format!("{} skipped", tests.skipped));
+        }
+        badge
+            .append_with_str_1(&counts.join(", "))
+            .map_err(Error::dom)?;
+        Dom::append(tests_container, &badge)?;
+
+        if tests.failing.is_empty() {
+            return Ok(());
+        }
+        let list = Dom::create_element(document, "ul")?;
+        list.set_class_name("failing-tests");
+        for name in &tests.failing {
+            let item = Dom::create_element(document, "li")?;
+            item.set_text_content(Some(name));
+            Dom::append(&list, &item)?;
+        }
+        let more = failed.saturating_sub(tests.failing.len() as u32);
+        if more > 0 {
+            let item = Dom::create_element(document, "li")?;
+            item.set_class_name("more");
+            item.set_text_content(Some(&format!("and {} more", more)));
+            Dom::append(&list, &item)?;
+        }
+        Dom::append(tests_container, &list)?;
+        Ok(())
     }
 
+    /// Render the jobs grouped by stage, stages in order of their first job.
     pub fn update_jobs(
         document: &web_sys::Document,
-        project_id: i32,
-        pipeline_id: i32,
-        jobs: &Vec<Job>,
-    ) {
-        let element_id = format!("pr{}_pl{}", project_id, pipeline_id);
-        let pipeline_container = document
-            .get_element_by_id(&element_id)
-            .expect("missing pipeline element");
+        project_key: &str,
+        pipeline_id: i64,
+        jobs: &[Job],
+    ) -> Result<(), Error> {
+        let element_id = format!("{}_pl{}", project_key, pipeline_id);
+        let pipeline_container = match document.get_element_by_id(&element_id) {
+            Some(pipeline_container) => pipeline_container,
+            None => return Ok(()),
+        };
 
-        for job in jobs {
-            let element_id = format!("pr{}_pl{}_{}", project_id, pipeline_id, &job.name);
-            let job_container = match document.get_element_by_id(&element_id) {
-                Some(job_container) => job_container,
-                None => {
-                    let job_container = document
-                        .create_element("div")
-                        .expect("Failed to create job container");
-                    job_container.set_id(&element_id);
-                    pipeline_container
-                        .append_child(&job_container)
-                        .expect("Failed to add job");
-                    job_container
-                }
-            };
+        let element_id = format!("{}_pl{}_stages", project_key, pipeline_id);
+        let stages_container = match document.get_element_by_id(&element_id) {
+            Some(stages_container) => stages_container,
+            None => {
+                let stages_container = Dom::create_element(document, "div")?;
+                stages_container.set_class_name("stages");
+                stages_container.set_id(&element_id);
+                Dom::append(&pipeline_container, &stages_container)?;
+                stages_container
+            }
+        };
+        // open logs are moved to the re-rendered jobs, keeping their content
+        let logs = stages_container.get_elements_by_class_name("log");
+        let open_logs: Vec<Element> = (0..logs.length())
+            .filter_map(|index| logs.item(index))
+            .filter(|log| !log.has_attribute("hidden"))
+            .collect();
+        stages_container.set_text_content(None);
 
-            match job.status {
-                Status::SUCCESS => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-check"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-success")
-                }
-                Status::FAILED => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-times-circle"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-fail");
-                }
-                Status::CANCELED => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-stop-circle"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-skipped");
-                }
-                Status::MANUAL => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-play"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-manual");
-                }
-                Status::RUNNING => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-cog fa-spin"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-running");
+        Dom::append_stages(
+            document,
+            &stages_container,
+            &format!("{}_pl{}", project_key, pipeline_id),
+            jobs,
+        )?;
+
+        for log in open_logs {
+            let job_container = log
+                .id()
+                .strip_suffix("_log")
+                .and_then(|job_id| document.get_element_by_id(job_id));
+            if let Some(job_container) = job_container {
+                // unless the job is not failed anymore, e.g. retried
+                if job_container
+                    .query_selector(".log-toggle")
+                    .map_err(Error::dom)?
+                    .is_some()
+                {
+                    Dom::append(&job_container, &log)?;
                 }
-                _ => {
-                    job_container.set_inner_html(&format!(
-                        r#"<i class="fas fa-minus-circle"></i><a href="{}" target="_blank">{}</a>"#,
-                        job.link, job.name
-                    ));
-                    job_container.set_class_name("job job-skipped");
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the jobs as stage columns, a bridge followed by its downstream pipeline.
+    fn append_stages(
+        document: &web_sys::Document,
+        parent: &Element,
+        pipeline_key: &str,
+        jobs: &[Job],
+    ) -> Result<(), Error> {
+        let mut stages: Vec<(&str, Vec<&Job>)> = vec![];
+        for job in jobs {
+            match stages.iter_mut().find(|(stage, _)| *stage == job.stage) {
+                Some((_, stage_jobs)) => stage_jobs.push(job),
+                None => stages.push((&job.stage, vec![job])),
+            }
+        }
+
+        for (stage, stage_jobs) in stages {
+            let stage_container = Dom::create_element(document, "div")?;
+            stage_container.set_class_name("stage");
+            if !stage.is_empty() {
+                let stage_name = Dom::create_element(document, "div")?;
+                stage_name.set_class_name("stage-name");
+                stage_name.set_text_content(Some(stage));
+                Dom::append(&stage_container, &stage_name)?;
+            }
+            for job in stage_jobs {
+                let element_id = format!("{}_{}", pipeline_key, &job.name);
+                Dom::append(
+                    &stage_container,
+                    &Dom::create_job(document, &element_id, job)?,
+                )?;
+                if let Some(downstream) = &job.downstream {
+                    let downstream_container =
+                        Dom::create_downstream(document, &element_id, downstream)?;
+                    Dom::append(&stage_container, &downstream_container)?;
                 }
             }
+            Dom::append(parent, &stage_container)?;
+        }
+        Ok(())
+    }
+
+    fn create_downstream(
+        document: &web_sys::Document,
+        job_key: &str,
+        downstream: &Downstream,
+    ) -> Result<Element, Error> {
+        let downstream_container = Dom::create_element(document, "div")?;
+        downstream_container.set_class_name(&format!(
+            "downstream {}",
+            Dom::map_status_to_bg(&downstream.status)
+        ));
+
+        let label = Dom::create_link(document, &downstream.link)?;
+        label.set_class_name("label");
+        label.set_text_content(Some(&format!("#{} / {}", downstream.id, downstream.r#ref)));
+        Dom::append(&downstream_container, &label)?;
+
+        let stages_container = Dom::create_element(document, "div")?;
+        stages_container.set_class_name("stages");
+        Dom::append_stages(
+            document,
+            &stages_container,
+            &format!("{}_pl{}", job_key, downstream.id),
+            &downstream.jobs,
+        )?;
+        Dom::append(&downstream_container, &stages_container)?;
+        Ok(downstream_container)
+    }
+
+    fn create_job(
+        document: &web_sys::Document,
+        element_id: &str,
+        job: &Job,
+    ) -> Result<Element, Error> {
+        let job_container = Dom::create_element(document, "div")?;
+        job_container.set_id(element_id);
+
+        Dom::set_job_status(document, &job_container, job.status)?;
+
+        let link = Dom::create_link(document, &job.link)?;
+        link.set_text_content(Some(&job.name));
+        Dom::append(&job_container, &link)?;
+        if let Some(log) = &job.log {
+            let button = Dom::create_element(document, "button")?;
+            button.set_class_name("log-toggle");
+            button
+                .set_attribute("data-target", log)
+                .map_err(Error::dom)?;
+            button
+                .set_attribute("title", &format!("Log of {}", job.name))
+                .map_err(Error::dom)?;
+            Dom::append(&button, &Dom::create_icon(document, "fa-file-alt")?)?;
+            Dom::append(&job_container, &button)?;
+        }
+        for action in &job.actions {
+            let title = format!("{} {}", action.kind.title(), job.name);
+            Dom::append(
+                &job_container,
+                &Dom::create_action_button(document, action, &title)?,
+            )?;
+        }
+        Ok(job_container)
+    }
+
+    /// Status class and icon of a job, the icon being its first child.
+    fn set_job_status(
+        document: &web_sys::Document,
+        job_container: &Element,
+        status: Status,
+    ) -> Result<(), Error> {
+        let (icon, class) = match status {
+            Status::SUCCESS => ("fa-check", "job-success"),
+            Status::FAILED => ("fa-times-circle", "job-fail"),
+            Status::CANCELED => ("fa-stop-circle", "job-skipped"),
+            Status::MANUAL => ("fa-play", "job-manual"),
+            Status::RUNNING => ("fa-cog fa-spin", "job-running"),
+            _ => ("fa-minus-circle", "job-skipped"),
+        };
+        job_container.set_class_name(&format!("job {}", class));
+        let icon = Dom::create_icon(document, icon)?;
+        match job_container.first_element_child() {
+            Some(previous) => previous
+                .replace_with_with_node_1(&icon)
+                .map_err(Error::dom)?,
+            None => Dom::append(job_container, &icon)?,
+        }
+        Ok(())
+    }
+
+    fn create_action_button(
+        document: &web_sys::Document,
+        action: &Action,
+        title: &str,
+    ) -> Result<Element, Error> {
+        let button = Dom::create_element(document, "button")?;
+        button.set_class_name("action");
+        button
+            .set_attribute("data-action", action.kind.name())
+            .map_err(Error::dom)?;
+        button
+            .set_attribute("data-target", &action.target)
+            .map_err(Error::dom)?;
+        button.set_attribute("title", title).map_err(Error::dom)?;
+        Dom::append(&button, &Dom::create_icon(document, action.kind.icon())?)?;
+        Ok(button)
+    }
+
+    /// Show or hide the log panel of the job of `button`. Returns the panel
+    /// if it was created, the log is to be loaded into it by `update_log`.
+    pub fn toggle_log(
+        document: &web_sys::Document,
+        button: &Element,
+    ) -> Result<Option<Element>, Error> {
+        let job_container = button
+            .closest(".job")
+            .map_err(Error::dom)?
+            .ok_or_else(|| Error::Dom("log button outside of a job".into()))?;
+        let element_id = format!("{}_log", job_container.id());
+        if let Some(panel) = document.get_element_by_id(&element_id) {
+            panel.toggle_attribute("hidden").map_err(Error::dom)?;
+            return Ok(None);
+        }
+        let panel = Dom::create_element(document, "pre")?;
+        panel.set_class_name("log");
+        panel.set_id(&element_id);
+        panel.set_text_content(Some("Loading log\u{2026}"));
+        Dom::append(&job_container, &panel)?;
+        Ok(Some(panel))
+    }
+
+    /// Display the log lines, or why they could not be requested.
+    pub fn update_log(panel: &Element, log: Result<&[String], &Error>) {
+        match log {
+            Ok([]) => panel.set_text_content(Some("Log is empty")),
+            Ok(lines) => panel.set_text_content(Some(&lines.join("\n"))),
+            Err(error) => {
+                panel.set_class_name("log log-error");
+                panel.set_text_content(Some(&format!("Log not available: {}", error)));
+            }
+        }
+    }
+
+    /// Show the expected outcome of an action on the job or pipeline of `button`
+    /// right away, the returned `PendingAction` undoes it if the action fails.
+    pub fn show_pending_action(
+        document: &web_sys::Document,
+        button: &Element,
+        kind: ActionKind,
+    ) -> Result<PendingAction, Error> {
+        let target = match button.closest(".job").map_err(Error::dom)? {
+            Some(job) => job,
+            None => button
+                .closest(".pipeline")
+                .map_err(Error::dom)?
+                .ok_or_else(|| Error::Dom("action outside of a job or pipeline".into()))?,
+        };
+        let pending = PendingAction {
+            class_name: target.class_name(),
+            // a pipeline keeps its children, only the icon of a job is replaced
+            icon: if Dom::has_class(&target, "job") {
+                target.first_element_child().map(|icon| icon.class_name())
+            } else {
+                None
+            },
+            target,
+        };
+
+        let status = kind.expected_status();
+        if Dom::has_class(&pending.target, "job") {
+            Dom::set_job_status(document, &pending.target, status)?;
+        } else {
+            pending
+                .target
+                .set_class_name(&format!("pipeline {}", Dom::map_status_to_bg(&status)));
+        }
+        button.set_attribute("disabled", "").map_err(Error::dom)?;
+        Ok(pending)
+    }
+}
+
+/// The job or pipeline of an action in flight, with its classes before the action.
+pub struct PendingAction {
+    target: Element,
+    class_name: String,
+    icon: Option<String>,
+}
+
+impl PendingAction {
+    /// Restore the job or pipeline as it was before the action and enable its buttons again.
+    pub fn undo(&self) {
+        self.target.set_class_name(&self.class_name);
+        if let (Some(icon), Some(class_name)) = (self.target.first_element_child(), &self.icon) {
+            icon.set_class_name(class_name);
+        }
+        let buttons = self.target.get_elements_by_class_name("action");
+        for index in 0..buttons.length() {
+            if let Some(button) = buttons.item(index) {
+                let _ = button.remove_attribute("disabled");
+            }
         }
     }
 }
@@ -0,0 +1,86 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Invalid configuration, e.g. an unparsable config or server URL.
+    Config(String),
+    /// The request did not get a response, e.g. server unreachable or CORS.
+    Network { url: String, message: String },
+    /// The server answered with an unexpected status code.
+    Http { url: String, status: u16 },
+    /// The token was rejected (401) or lacks permissions (403).
+    Auth { url: String, status: u16 },
+    /// Too many requests (429).
+    RateLimit { url: String },
+    /// The response body did not match the expected format.
+    Decode { url: String, message: String },
+    /// The server rejected the request, e.g. errors of a GraphQL query.
+    Api { url: String, message: String },
+    /// Creating or updating the page failed.
+    Dom(String),
+}
+
+impl Error {
+    pub fn dom(value: JsValue) -> Self {
+        Error::Dom(js_message(&value))
+    }
+
+    pub fn network(url: &str, value: JsValue) -> Self {
+        Error::Network {
+            url: url.to_string(),
+            message: js_message(&value),
+        }
+    }
+
+    pub fn decode(url: &str, message: impl ToString) -> Self {
+        Error::Decode {
+            url: url.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Map a non-2xx HTTP status onto the matching error.
+    pub fn from_status(url: &str, status: u16) -> Self {
+        let url = url.to_string();
+        match status {
+            401 | 403 => Error::Auth { url, status },
+            429 => Error::RateLimit { url },
+            _ => Error::Http { url, status },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "Invalid config: {}", message),
+            Error::Network { url, message } => write!(f, "Network error for {}: {}", url, message),
+            Error::Http { url, status } => write!(f, "HTTP status {} for {}", status, url),
+            Error::Auth { url, status } => {
+                write!(f, "Authentication failed ({}) for {}", status, url)
+            }
+            Error::RateLimit { url } => write!(f, "Rate limited for {}", url),
+            Error::Decode { url, message } => {
+                write!(f, "Failed to parse response for {}: {}", url, message)
+            }
+            Error::Api { url, message } => write!(f, "Request rejected by {}: {}", url, message),
+            Error::Dom(message) => write!(f, "Failed to update page: {}", message),
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> Self {
+        JsValue::from(error.to_string())
+    }
+}
+
+/// Readable message of a javascript exception or value.
+pub fn js_message(value: &JsValue) -> String {
+    match value.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => value.as_string().unwrap_or_else(|| format!("{:?}", value)),
+    }
+}
@@ -0,0 +1,308 @@
+use crate::dom;
+use crate::error::{js_message, Error};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+
+/// Selects the projects of a source to display.
+///
+/// A project is shown if it matches any of `ids`, `paths`, `namespaces` or
+/// `namespace_regex` (or none of them is set) and does not match `exclude`.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ProjectFilter {
+    /// Project ids as used by the provider, e.g. `42` or `owner/repo`.
+    #[serde(default)]
+    ids: Vec<String>,
+    /// Glob patterns on `group/name`.
+    #[serde(default)]
+    paths: Vec<String>,
+    /// Glob patterns on the group (namespace) name.
+    #[serde(default)]
+    namespaces: Vec<String>,
+    /// Regular expression on the group (namespace) name.
+    #[serde(default)]
+    namespace_regex: Option<String>,
+    /// `namespace_regex` compiled once when the config is parsed.
+    #[serde(skip)]
+    compiled_regex: Option<js_sys::RegExp>,
+    /// Glob patterns on `group/name` of projects to hide.
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// GitLab only, show archived projects (`true`) or only active ones (`false`).
+    #[serde(default)]
+    pub archived: Option<bool>,
+    /// GitLab only, show starred projects only.
+    #[serde(default)]
+    pub starred: Option<bool>,
+}
+
+impl ProjectFilter {
+    /// Compile `namespace_regex`, an invalid expression is a config error.
+    pub fn compile(&mut self) -> Result<(), Error> {
+        self.compiled_regex = match &self.namespace_regex {
+            Some(pattern) => Some(new_regexp(pattern).map_err(|e| {
+                Error::Config(format!(
+                    "Invalid namespace_regex {}: {}",
+                    pattern,
+                    js_message(&e)
+                ))
+            })?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    pub fn apply(&self, projects: Vec<dom::Project>) -> Vec<dom::Project> {
+        projects.into_iter().filter(|p| self.matches(p)).collect()
+    }
+
+    fn matches(&self, project: &dom::Project) -> bool {
+        let path = format!("{}/{}", project.group, project.name);
+        let included = (self.ids.is_empty()
+            && self.paths.is_empty()
+            && self.namespaces.is_empty()
+            && self.namespace_regex.is_none())
+            || self.ids.contains(&project.id)
+            || self.paths.iter().any(|pattern| glob(pattern, &path))
+            || self
+                .namespaces
+                .iter()
+                .any(|pattern| glob(pattern, &project.group))
+            || self
+                .compiled_regex
+                .as_ref()
+                .is_some_and(|regex| regex.test(&project.group));
+
+        included && !self.exclude.iter().any(|pattern| glob(pattern, &path))
+    }
+}
+
+/// `new RegExp(pattern)`, returning the `SyntaxError` of an invalid pattern
+/// instead of throwing it through wasm like `js_sys::RegExp::new`.
+fn new_regexp(pattern: &str) -> Result<js_sys::RegExp, JsValue> {
+    let constructor: js_sys::Function =
+        js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("RegExp"))?.dyn_into()?;
+    let regexp =
+        js_sys::Reflect::construct(&constructor, &js_sys::Array::of1(&JsValue::from(pattern)))?;
+    Ok(regexp.unchecked_into())
+}
+
+/// Match a glob pattern supporting `*` (any sequence) and `?` (any character).
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Selects the pipelines of a project to display by their ref.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct RefFilter {
+    /// Branch or tag names, glob patterns are supported.
+    #[serde(default)]
+    pub refs: Vec<String>,
+    /// Show pipelines of the project's default branch only.
+    #[serde(default)]
+    pub default_branch: bool,
+    /// Show tag pipelines only (GitLab).
+    #[serde(default)]
+    pub tags: bool,
+    /// Show merge/pull request pipelines only (GitLab, GitHub).
+    #[serde(default)]
+    pub merge_requests: bool,
+}
+
+impl RefFilter {
+    /// The single ref to request from the server, if the filter selects exactly one.
+    pub fn single_ref<'a>(&'a self, project: &'a dom::Project) -> Option<&'a str> {
+        if self.default_branch {
+            return project.default_branch.as_deref();
+        }
+        match self.refs.as_slice() {
+            [name] if !name.contains(['*', '?']) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Client side filtering for what could not be passed on to the server.
+    pub fn apply(
+        &self,
+        project: &dom::Project,
+        pipelines: Vec<dom::Pipeline>,
+    ) -> Vec<dom::Pipeline> {
+        pipelines
+            .into_iter()
+            .filter(|p| self.matches(project, p))
+            .collect()
+    }
+
+    fn matches(&self, project: &dom::Project, pipeline: &dom::Pipeline) -> bool {
+        self.matches_ref(project, &pipeline.r#ref)
+    }
+
+    pub fn matches_ref(&self, project: &dom::Project, r#ref: &str) -> bool {
+        if self.default_branch {
+            if let Some(default_branch) = &project.default_branch {
+                if r#ref != default_branch {
+                    return false;
+                }
+            }
+        }
+        self.refs.is_empty() || self.refs.iter().any(|pattern| glob(pattern, r#ref))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(group: &str, name: &str) -> dom::Project {
+        dom::Project {
+            id: format!("{}/{}", group, name),
+            name: name.to_string(),
+            group: group.to_string(),
+            default_branch: None,
+        }
+    }
+
+    fn with_default_branch(default_branch: Option<&str>) -> dom::Project {
+        dom::Project {
+            default_branch: default_branch.map(str::to_string),
+            ..project("backend", "api")
+        }
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn star_matches_empty_and_several_segments() {
+        assert!(glob("backend/*", "backend/"));
+        assert!(glob("backend/*", "backend/api"));
+        assert!(glob("*/api", "backend/services/api"));
+        assert!(glob("*", ""));
+        assert!(!glob("backend/*", "frontend/api"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(glob("team-?", "team-a"));
+        assert!(!glob("team-?", "team-"));
+        assert!(!glob("team-?", "team-ab"));
+    }
+
+    #[test]
+    fn trailing_star_matches_rest() {
+        assert!(glob("release/*", "release/1.0"));
+        assert!(glob("team-**", "team-"));
+        assert!(!glob("release/*", "release"));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(glob("a*b*c", "axxbyyc"));
+        assert!(glob("a*b*c", "abbcbc"));
+        assert!(!glob("a*b*c", "axxbyy"));
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = ProjectFilter::default();
+        assert!(filter.matches(&project("backend", "api")));
+        assert!(filter.matches(&project("", "")));
+    }
+
+    #[test]
+    fn exclude_overrides_include() {
+        let filter = ProjectFilter {
+            paths: patterns(&["backend/*"]),
+            exclude: patterns(&["*/sandbox-*"]),
+            ..Default::default()
+        };
+        assert!(filter.matches(&project("backend", "api")));
+        assert!(!filter.matches(&project("backend", "sandbox-api")));
+        assert!(!filter.matches(&project("frontend", "web")));
+    }
+
+    #[test]
+    fn any_include_matches() {
+        let filter = ProjectFilter {
+            ids: patterns(&["ops/deploy"]),
+            namespaces: patterns(&["team-*"]),
+            ..Default::default()
+        };
+        assert!(filter.matches(&project("ops", "deploy")));
+        assert!(filter.matches(&project("team-web", "site")));
+        assert!(!filter.matches(&project("ops", "monitoring")));
+    }
+
+    #[test]
+    fn single_ref_only_for_one_exact_name() {
+        let project = with_default_branch(Some("main"));
+        let refs = |names: &[&str]| RefFilter {
+            refs: patterns(names),
+            ..Default::default()
+        };
+        assert_eq!(refs(&["main"]).single_ref(&project), Some("main"));
+        assert_eq!(refs(&["release/*"]).single_ref(&project), None);
+        assert_eq!(refs(&["main", "develop"]).single_ref(&project), None);
+        assert_eq!(refs(&[]).single_ref(&project), None);
+    }
+
+    #[test]
+    fn single_ref_of_default_branch() {
+        let filter = RefFilter {
+            default_branch: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.single_ref(&with_default_branch(Some("main"))),
+            Some("main")
+        );
+        assert_eq!(filter.single_ref(&with_default_branch(None)), None);
+    }
+
+    #[test]
+    fn refs_matched_by_pattern() {
+        let project = with_default_branch(Some("main"));
+        let filter = RefFilter {
+            refs: patterns(&["main", "release/*"]),
+            ..Default::default()
+        };
+        assert!(filter.matches_ref(&project, "main"));
+        assert!(filter.matches_ref(&project, "release/1.0"));
+        assert!(!filter.matches_ref(&project, "feature/x"));
+        assert!(RefFilter::default().matches_ref(&project, "feature/x"));
+    }
+
+    #[test]
+    fn default_branch_matched_if_known() {
+        let filter = RefFilter {
+            default_branch: true,
+            ..Default::default()
+        };
+        let project = with_default_branch(Some("main"));
+        assert!(filter.matches_ref(&project, "main"));
+        assert!(!filter.matches_ref(&project, "develop"));
+        // without a known default branch no pipeline is hidden
+        let project = with_default_branch(None);
+        assert!(filter.matches_ref(&project, "develop"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::config::SourceConfig;
+use crate::dom;
+use crate::github;
+use crate::provider::{self, CiProvider, Fetched, ProviderFuture};
+use futures::{FutureExt, TryFutureExt};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Maximum page size of Gitea and Forgejo with their default `MAX_RESPONSE_ITEMS`.
+const PER_PAGE: u32 = 50;
+
+#[derive(Deserialize)]
+struct GiteaJob {
+    name: String,
+    status: String,
+    conclusion: Option<String>,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaJobs {
+    jobs: Vec<GiteaJob>,
+}
+
+#[derive(Deserialize)]
+struct GiteaRun {
+    id: i64,
+    status: String,
+    conclusion: Option<String>,
+    head_branch: Option<String>,
+    #[serde(alias = "started")]
+    started_at: Option<String>,
+    #[serde(alias = "stopped")]
+    completed_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GiteaRuns {
+    workflow_runs: Vec<GiteaRun>,
+}
+
+#[derive(Deserialize)]
+struct GiteaOwner {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaRepository {
+    name: String,
+    full_name: String,
+    owner: GiteaOwner,
+    default_branch: Option<String>,
+}
+
+pub struct Gitea {
+    config: Arc<SourceConfig>,
+    client: provider::Client,
+}
+
+impl Gitea {
+    pub fn new(config: Arc<SourceConfig>, client: provider::Client) -> Self {
+        let client = client.with_header("Authorization", format!("token {}", config.token));
+        Gitea { config, client }
+    }
+}
+
+impl CiProvider for Gitea {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>> {
+        let mut next_url = Some(format!(
+            "{}/api/v1/user/repos?limit={}",
+            self.config.server, PER_PAGE
+        ));
+        let client = self.client.clone();
+        let max_pages = self.config.max_pages;
+        async move {
+            let mut repositories = vec![];
+            let mut pages = 0;
+            while let Some(url) = next_url.take() {
+                let (page, headers) = client
+                    .fetch_json_with_headers::<Vec<GiteaRepository>>(client.new_request(&url)?)
+                    .await?;
+                repositories.extend(page);
+                pages += 1;
+                if pages < max_pages {
+                    next_url = provider::next_link(&headers);
+                }
+            }
+            Ok(repositories
+                .into_iter()
+                .map(|r| dom::Project {
+                    id: r.full_name,
+                    name: r.name,
+                    group: r.owner.login,
+                    default_branch: r.default_branch,
+                })
+                .collect())
+        }
+        .boxed_local()
+    }
+
+    fn request_pipelines(
+        &self,
+        project: &dom::Project,
+        _limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let url = format!(
+            "{}/api/v1/repos/{}/actions/runs",
+            self.config.server, project.id
+        );
+        self.client
+            .prepare_request::<GiteaRuns>(&url)
+            .map_ok(|runs| {
+                runs.workflow_runs
+                    .into_iter()
+                    .map(|r| dom::Pipeline {
+                        id: r.id,
+                        status: map_status(&r.status, r.conclusion.as_deref()),
+                        r#ref: r.head_branch.unwrap_or_default(),
+                        merge_request: None,
+                    })
+                    .collect()
+            })
+            .map_ok(Fetched::new)
+            .boxed_local()
+    }
+
+    fn request_pipeline_detail(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail> {
+        let url = format!(
+            "{}/api/v1/repos/{}/actions/runs/{}",
+            self.config.server, project_id, pipeline_id
+        );
+        self.client
+            .prepare_request::<GiteaRun>(&url)
+            .map_ok(|run| dom::PipelineDetail {
+                id: run.id,
+                status: map_status(&run.status, run.conclusion.as_deref()),
+                duration: provider::seconds_between(
+                    run.started_at.as_deref(),
+                    run.completed_at.as_deref(),
+                ),
+                r#ref: run.head_branch.unwrap_or_default(),
+                actions: vec![],
+                tests: None,
+            })
+            .boxed_local()
+    }
+
+    fn request_jobs(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>> {
+        let url = format!(
+            "{}/api/v1/repos/{}/actions/runs/{}/jobs",
+            self.config.server, project_id, pipeline_id
+        );
+        self.client
+            .prepare_request::<GiteaJobs>(&url)
+            .map_ok(|jobs| {
+                jobs.jobs
+                    .into_iter()
+                    .map(|j| dom::Job {
+                        status: map_status(&j.status, j.conclusion.as_deref()),
+                        name: j.name,
+                        stage: String::new(),
+                        downstream: None,
+                        link: j.html_url,
+                        log: None,
+                        actions: vec![],
+                    })
+                    .collect()
+            })
+            .map_ok(Fetched::new)
+            .boxed_local()
+    }
+}
+
+/// Gitea follows the GitHub status/conclusion pairs, Forgejo reports the
+/// result directly as status.
+fn map_status(status: &str, conclusion: Option<&str>) -> dom::Status {
+    match status {
+        "success" => dom::Status::SUCCESS,
+        "failure" => dom::Status::FAILED,
+        "cancelled" => dom::Status::CANCELED,
+        "skipped" => dom::Status::SKIPPED,
+        "running" => dom::Status::RUNNING,
+        "waiting" => dom::Status::PENDING,
+        "blocked" => dom::Status::MANUAL,
+        _ => github::map_status(status, conclusion),
+    }
+}
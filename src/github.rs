@@ -0,0 +1,212 @@
+use crate::config::SourceConfig;
+use crate::dom;
+use crate::provider::{self, CiProvider, Fetched, ProviderFuture};
+use futures::{FutureExt, TryFutureExt};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Maximum page size of the GitHub REST API.
+const PER_PAGE: u32 = 100;
+
+#[derive(Deserialize)]
+struct GitHubJob {
+    name: String,
+    status: String,
+    conclusion: Option<String>,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubJobs {
+    jobs: Vec<GitHubJob>,
+}
+
+#[derive(Deserialize)]
+struct GitHubWorkflowRun {
+    id: i64,
+    status: String,
+    conclusion: Option<String>,
+    head_branch: Option<String>,
+    run_started_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubWorkflowRuns {
+    workflow_runs: Vec<GitHubWorkflowRun>,
+}
+
+#[derive(Deserialize)]
+struct GitHubOwner {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubRepository {
+    name: String,
+    full_name: String,
+    owner: GitHubOwner,
+    default_branch: Option<String>,
+}
+
+pub struct GitHub {
+    config: Arc<SourceConfig>,
+    client: provider::Client,
+}
+
+impl GitHub {
+    pub fn new(config: Arc<SourceConfig>, client: provider::Client) -> Self {
+        let client = client
+            .with_header("Accept", "application/vnd.github+json".into())
+            .with_header("Authorization", format!("Bearer {}", config.token));
+        GitHub { config, client }
+    }
+}
+
+impl CiProvider for GitHub {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>> {
+        let mut next_url = Some(format!(
+            "{}/user/repos?sort=pushed&per_page={}",
+            self.config.server, PER_PAGE
+        ));
+        let client = self.client.clone();
+        let max_pages = self.config.max_pages;
+        async move {
+            let mut repositories = vec![];
+            let mut pages = 0;
+            while let Some(url) = next_url.take() {
+                let (page, headers) = client
+                    .fetch_json_with_headers::<Vec<GitHubRepository>>(client.new_request(&url)?)
+                    .await?;
+                repositories.extend(page);
+                pages += 1;
+                if pages < max_pages {
+                    next_url = provider::next_link(&headers);
+                }
+            }
+            Ok(repositories
+                .into_iter()
+                .map(|r| dom::Project {
+                    id: r.full_name,
+                    name: r.name,
+                    group: r.owner.login,
+                    default_branch: r.default_branch,
+                })
+                .collect())
+        }
+        .boxed_local()
+    }
+
+    fn request_pipelines(
+        &self,
+        project: &dom::Project,
+        _limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let refs = self.config.refs_for(project);
+        let mut parameters = vec![];
+        if let Some(name) = refs.single_ref(project) {
+            let name = String::from(js_sys::encode_uri_component(name));
+            parameters.push(format!("branch={}", name));
+        }
+        if refs.merge_requests {
+            parameters.push("event=pull_request".to_string());
+        }
+        let mut url = format!("{}/repos/{}/actions/runs", self.config.server, project.id);
+        if !parameters.is_empty() {
+            url = format!("{}?{}", url, parameters.join("&"));
+        }
+        self.client
+            .prepare_request::<GitHubWorkflowRuns>(&url)
+            .map_ok(|runs| {
+                runs.workflow_runs
+                    .into_iter()
+                    .map(|r| dom::Pipeline {
+                        id: r.id,
+                        status: map_status(&r.status, r.conclusion.as_deref()),
+                        r#ref: r.head_branch.unwrap_or_default(),
+                        merge_request: None,
+                    })
+                    .collect()
+            })
+            .map_ok(Fetched::new)
+            .boxed_local()
+    }
+
+    fn request_pipeline_detail(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail> {
+        let url = format!(
+            "{}/repos/{}/actions/runs/{}",
+            self.config.server, project_id, pipeline_id
+        );
+        self.client
+            .prepare_request::<GitHubWorkflowRun>(&url)
+            .map_ok(|run| dom::PipelineDetail {
+                id: run.id,
+                status: map_status(&run.status, run.conclusion.as_deref()),
+                duration: duration(&run),
+                r#ref: run.head_branch.unwrap_or_default(),
+                actions: vec![],
+                tests: None,
+            })
+            .boxed_local()
+    }
+
+    fn request_jobs(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>> {
+        let url = format!(
+            "{}/repos/{}/actions/runs/{}/jobs",
+            self.config.server, project_id, pipeline_id
+        );
+        self.client
+            .prepare_request::<GitHubJobs>(&url)
+            .map_ok(|jobs| {
+                jobs.jobs
+                    .into_iter()
+                    .map(|j| dom::Job {
+                        status: map_status(&j.status, j.conclusion.as_deref()),
+                        name: j.name,
+                        stage: String::new(),
+                        downstream: None,
+                        link: j.html_url,
+                        log: None,
+                        actions: vec![],
+                    })
+                    .collect()
+            })
+            .map_ok(Fetched::new)
+            .boxed_local()
+    }
+}
+
+/// GitHub does not report a duration itself, take the time until the last update.
+fn duration(run: &GitHubWorkflowRun) -> i32 {
+    if run.status != "completed" {
+        return 0;
+    }
+    provider::seconds_between(run.run_started_at.as_deref(), run.updated_at.as_deref())
+}
+
+pub(crate) fn map_status(status: &str, conclusion: Option<&str>) -> dom::Status {
+    match (status, conclusion) {
+        ("completed", Some("success")) => dom::Status::SUCCESS,
+        ("completed", Some("failure"))
+        | ("completed", Some("timed_out"))
+        | ("completed", Some("startup_failure")) => dom::Status::FAILED,
+        ("completed", Some("cancelled")) => dom::Status::CANCELED,
+        ("completed", Some("skipped"))
+        | ("completed", Some("neutral"))
+        | ("completed", Some("stale")) => dom::Status::SKIPPED,
+        ("completed", Some("action_required")) | ("waiting", _) => dom::Status::MANUAL,
+        ("in_progress", _) => dom::Status::RUNNING,
+        ("queued", _) | ("requested", _) | ("pending", _) => dom::Status::PENDING,
+        // unknown conclusions are neutral, unknown states are requested again
+        ("completed", _) => dom::Status::SKIPPED,
+        _ => dom::Status::PENDING,
+    }
+}
@@ -1,232 +1,681 @@
+use crate::config::SourceConfig;
 use crate::dom;
-use crate::Config;
-use futures::TryFutureExt;
-use futures::{future, Future};
+use crate::error::Error;
+use crate::provider::{self, CiProvider, Fetched, ProviderFuture};
+use futures::{Future, FutureExt, TryFutureExt};
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::Deserialize;
 use std::sync::Arc;
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::console;
-use web_sys::{Request, RequestInit, RequestMode, Response};
+use wasm_bindgen::JsValue;
+use web_sys::{console, Headers};
+
+const PER_PAGE: u32 = 100;
+/// Names of failed tests listed per pipeline.
+const MAX_FAILING_TESTS: usize = 10;
 
 #[derive(Deserialize, Clone)]
 struct GitLabJob {
-    id: i32,
+    id: i64,
     name: String,
+    stage: String,
     status: String,
     web_url: String,
+    /// Replaced by a newer run of the same job, listed with `include_retried=true`.
+    #[serde(default)]
+    retried: bool,
+}
+
+/// A trigger job, starting a downstream or child pipeline.
+#[derive(Deserialize, Clone)]
+struct GitLabBridge {
+    id: i64,
+    name: String,
+    stage: String,
+    status: String,
+    web_url: String,
+    downstream_pipeline: Option<GitLabDownstreamPipeline>,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabDownstreamPipeline {
+    id: i64,
+    project_id: i64,
+    status: String,
+    r#ref: String,
+    web_url: String,
 }
 
 #[derive(Deserialize)]
 struct GitLabPipelineDetail {
-    id: i32,
+    id: i64,
     status: String,
     r#ref: String,
     duration: Option<i32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+struct GitLabTestReportSummary {
+    total: GitLabTestCounts,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabTestCounts {
+    count: u32,
+    failed: u32,
+    skipped: u32,
+    error: u32,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabTestReport {
+    test_suites: Vec<GitLabTestSuite>,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabTestSuite {
+    test_cases: Vec<GitLabTestCase>,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabTestCase {
+    name: String,
+    classname: Option<String>,
+    status: String,
+}
+
+#[derive(Deserialize, Clone)]
 struct GitLabPipeline {
-    id: i32,
+    id: i64,
     status: String,
+    r#ref: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+struct GitLabMergeRequest {
+    iid: i64,
+    title: String,
+    author: GitLabUser,
+    target_branch: String,
+    /// Older servers only know `work_in_progress`.
+    #[serde(default, alias = "work_in_progress")]
+    draft: bool,
+    web_url: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct GitLabUser {
+    name: String,
+}
+
+#[derive(Deserialize, Clone)]
 struct GitLabNameSpace {
     name: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct GitLabProject {
     id: i32,
     name: String,
     namespace: GitLabNameSpace,
+    default_branch: Option<String>,
 }
 
+#[derive(Clone)]
 pub struct GitLab {
-    config: Arc<Config>,
+    config: Arc<SourceConfig>,
+    client: provider::Client,
 }
 
 impl GitLab {
-    pub fn new(config: Arc<Config>) -> Self {
-        GitLab { config }
+    pub fn new(config: Arc<SourceConfig>, client: provider::Client) -> Self {
+        let client = client.with_header("Private-Token", config.token.clone());
+        GitLab { config, client }
     }
 
-    fn set_request_headers(&self, request: &Request) {
-        let headers = request.headers();
-        headers
-            .set("Accept", "application/json")
-            .expect("Failed to set accept header");
-        headers
-            .set("Private-Token", &self.config.token)
-            .expect("Failed to set auth header");
+    /// Conditional request, unchanged responses are taken from the cache.
+    fn prepare_cached_request<T: DeserializeOwned + Clone + 'static>(
+        &self,
+        url: &str,
+    ) -> impl Future<Output = Result<Fetched<T>, Error>> {
+        let request = self.client.new_request(url);
+        let client = self.client.clone();
+        async move {
+            let fetched = client.fetch_json_cached(request?).await?;
+            Ok(fetched.map(|(value, _)| value))
+        }
     }
 
-    fn prepare_request(&self, url: &str) -> impl Future<Output = Result<JsValue, JsValue>> {
-        let window = web_sys::window().expect("no global `window` exists");
-
-        let mut opts = RequestInit::new();
-        opts.method("GET");
-        opts.mode(RequestMode::Cors);
-
-        let request =
-            Request::new_with_str_and_init(url, &opts).expect("Failed to initialize request");
-        self.set_request_headers(&request);
-
-        let request_promise = window.fetch_with_request(&request);
+    /// Request all pages of a list endpoint, following the `Link` or `X-Next-Page`
+    /// headers until the last page or `max_pages` is reached. Pages are requested
+    /// conditionally, the list is modified if any page is.
+    fn prepare_paged_request<T: DeserializeOwned + Clone + 'static>(
+        &self,
+        url: &str,
+    ) -> impl Future<Output = Result<Fetched<Vec<T>>, Error>> {
+        self.prepare_paged_request_until(url, |_| false)
+    }
 
-        JsFuture::from(request_promise)
-            .and_then(|jsvalue| {
-                futures::future::ready(jsvalue.dyn_into().and_then(|r: Response| r.json()))
+    /// Like `prepare_paged_request`, stopping early once `enough` holds for the items so far.
+    fn prepare_paged_request_until<T: DeserializeOwned + Clone + 'static>(
+        &self,
+        url: &str,
+        enough: impl Fn(&[T]) -> bool + 'static,
+    ) -> impl Future<Output = Result<Fetched<Vec<T>>, Error>> {
+        let gitlab = self.clone();
+        let mut next_url = Some(with_query(url, &format!("per_page={}", PER_PAGE)));
+        async move {
+            let mut items = vec![];
+            let mut modified = false;
+            let mut pages = 0;
+            while let Some(url) = next_url.take() {
+                let fetched: Fetched<(Vec<T>, Headers)> = gitlab
+                    .client
+                    .fetch_json_cached(gitlab.client.new_request(&url)?)
+                    .await?;
+                let (page, headers) = fetched.value;
+                items.extend(page);
+                modified |= fetched.modified;
+                pages += 1;
+                if pages < gitlab.config.max_pages && !enough(&items) {
+                    next_url = next_page_url(&url, &headers);
+                }
+            }
+            Ok(Fetched {
+                value: items,
+                modified,
             })
-            .and_then(|json_promise| JsFuture::from(json_promise))
-    }
-
-    pub fn request_projects(&self) -> impl Future<Output = Result<Vec<dom::Project>, JsValue>> {
-        // console::log_1(&JsValue::from("Request projects"));
-        let url = format!("{}/api/v4/projects?membership=true", self.config.server);
-        let request_future = self
-            .prepare_request(&url)
-            .and_then(move |jsvalue| {
-                futures::future::ready(jsvalue.into_serde::<Vec<GitLabProject>>().map_err(|e| {
-                    JsValue::from(&format!(
-                        "Failed to parse response for {}: {}",
-                        &url,
-                        e.to_string()
-                    ))
-                }))
+        }
+    }
+}
+
+impl CiProvider for GitLab {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>> {
+        let mut url = format!("{}/api/v4/projects?membership=true", self.config.server);
+        if let Some(archived) = self.config.filter.archived {
+            url = with_query(&url, &format!("archived={}", archived));
+        }
+        if let Some(starred) = self.config.filter.starred {
+            url = with_query(&url, &format!("starred={}", starred));
+        }
+        self.prepare_paged_request::<GitLabProject>(&url)
+            .map_ok(|projects| {
+                projects
+                    .value
+                    .into_iter()
+                    .map(|p| dom::Project {
+                        id: p.id.to_string(),
+                        name: p.name,
+                        group: p.namespace.name,
+                        default_branch: p.default_branch,
+                    })
+                    .collect()
             })
-            .and_then(|projects| {
-                future::ok(
-                    projects
-                        .into_iter()
-                        .map(|p| dom::Project {
-                            id: p.id,
-                            name: p.name,
-                            group: p.namespace.name,
-                        })
-                        .collect::<Vec<dom::Project>>(),
-                )
-            });
-        request_future
+            .boxed_local()
     }
 
-    pub fn request_pipelines(
+    fn request_pipelines(
         &self,
-        project_id: i32,
-    ) -> impl Future<Output = Result<Vec<dom::Pipeline>, JsValue>> {
-        // console::log_1(&JsValue::from(&format!(
-        //     "Request pipelines for project {}",
-        //     project_id
-        // )));
-        let url = format!(
+        project: &dom::Project,
+        limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let refs = self.config.refs_for(project);
+        let mut url = format!(
             "{}/api/v4/projects/{}/pipelines?order_by=id&sort=desc",
-            self.config.server, project_id
+            self.config.server, project.id
         );
-        let request_future = self
-            .prepare_request(&url)
-            .and_then(move |jsvalue| {
-                futures::future::ready(jsvalue.into_serde::<Vec<GitLabPipeline>>().map_err(|e| {
-                    JsValue::from(&format!(
-                        "Failed to parse response for {}: {}",
-                        &url,
-                        e.to_string()
-                    ))
-                }))
+        let single_ref = refs.single_ref(project);
+        if let Some(name) = single_ref {
+            let name = String::from(js_sys::encode_uri_component(name));
+            url = with_query(&url, &format!("ref={}", name));
+        }
+        if refs.tags {
+            url = with_query(&url, "scope=tags");
+        }
+        if refs.merge_requests {
+            url = with_query(&url, "source=merge_request_event");
+        }
+
+        let pipelines = if single_ref.is_some() || (refs.refs.is_empty() && !refs.default_branch) {
+            self.prepare_cached_request::<Vec<GitLabPipeline>>(&url)
+                .boxed_local()
+        } else {
+            // patterns and several refs are matched client side,
+            // pages are requested until enough pipelines match
+            let project = project.clone();
+            self.prepare_paged_request_until(&url, move |pipelines: &[GitLabPipeline]| {
+                pipelines
+                    .iter()
+                    .filter(|p| refs.matches_ref(&project, &p.r#ref))
+                    .count()
+                    >= limit
             })
-            .and_then(|projects| {
-                future::ok(
-                    projects
+            .boxed_local()
+        };
+        pipelines
+            .map_ok(|pipelines| {
+                pipelines.map(|pipelines| {
+                    pipelines
                         .into_iter()
                         .map(|p| dom::Pipeline {
                             id: p.id,
                             status: map_status(&p.status),
+                            r#ref: p.r#ref,
+                            merge_request: None,
                         })
-                        .collect::<Vec<dom::Pipeline>>(),
-                )
-            });
-        request_future
+                        .collect()
+                })
+            })
+            .boxed_local()
     }
 
-    pub fn request_pipeline_detail(
+    fn request_pipeline_detail(
         &self,
-        project_id: i32,
-        pipeline_id: i32,
-    ) -> impl Future<Output = Result<dom::PipelineDetail, JsValue>> {
-        // console::log_1(&JsValue::from(&format!(
-        //     "Request pipeline detail for project {}, pipeline {}",
-        //     project_id, pipeline_id
-        // )));
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail> {
         let url = format!(
             "{}/api/v4/projects/{}/pipelines/{}",
             self.config.server, project_id, pipeline_id
         );
-        let request_future = self
-            .prepare_request(&url)
-            .and_then(move |jsvalue| {
-                futures::future::ready(jsvalue.into_serde::<GitLabPipelineDetail>().map_err(|e| {
-                    JsValue::from(&format!(
-                        "Failed to parse response for {}: {}",
-                        &url,
-                        e.to_string()
-                    ))
-                }))
+        let gitlab = self.clone();
+        let project_id = project_id.to_string();
+        async move {
+            let pipeline = gitlab
+                .client
+                .prepare_request::<GitLabPipelineDetail>(&url)
+                .await?;
+            // the summary is optional, the pipeline is shown without it
+            let tests = match gitlab.request_test_summary(&url).await {
+                Ok(tests) => tests,
+                Err(error) => {
+                    console::log_1(&JsValue::from(format!(
+                        "Test report of pipeline {} not available: {}",
+                        pipeline.id, error
+                    )));
+                    None
+                }
+            };
+            let status = map_status(&pipeline.status);
+            let target = format!("projects/{}/pipelines/{}", project_id, pipeline.id);
+            Ok(dom::PipelineDetail {
+                id: pipeline.id,
+                status,
+                r#ref: pipeline.r#ref,
+                duration: pipeline.duration.unwrap_or(0),
+                actions: gitlab.actions(target, status, false),
+                tests,
             })
-            .and_then(|pipeline| {
-                future::ok(dom::PipelineDetail {
-                    id: pipeline.id,
-                    status: map_status(&pipeline.status),
-                    r#ref: pipeline.r#ref,
-                    duration: pipeline.duration.unwrap_or(0),
-                })
-            });
-        request_future
+        }
+        .boxed_local()
     }
 
-    pub fn request_jobs(
+    fn request_jobs(
         &self,
-        project_id: i32,
-        pipeline_id: i32,
-    ) -> impl Future<Output = Result<Vec<dom::Job>, JsValue>> {
-        // console::log_1(&JsValue::from(&format!(
-        //     "Request jobs for project {}, pipeline {}",
-        //     project_id, pipeline_id
-        // )));
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>> {
         let url = format!(
-            "{}/api/v4/projects/{}/pipelines/{}/jobs",
+            "{}/api/v4/projects/{}/pipelines/{}",
             self.config.server, project_id, pipeline_id
         );
-        let request_future = self
-            .prepare_request(&url)
-            .and_then(move |jsvalue| {
-                futures::future::ready(jsvalue.into_serde::<Vec<GitLabJob>>().map_err(|e| {
-                    JsValue::from(&format!(
-                        "Failed to parse response for {}: {}",
-                        &url,
-                        e.to_string()
+        let gitlab = self.clone();
+        let project_id = project_id.to_string();
+        async move {
+            let jobs = gitlab
+                .prepare_paged_request::<GitLabJob>(&format!("{}/jobs?include_retried=true", url))
+                .await?;
+            let bridges = gitlab
+                .prepare_paged_request::<GitLabBridge>(&format!("{}/bridges", url))
+                .await?;
+            let mut modified = jobs.modified || bridges.modified;
+
+            let mut all: Vec<(i64, bool, dom::Job)> = jobs
+                .value
+                .into_iter()
+                .map(|j| (j.id, j.retried, gitlab.map_job(&project_id, j)))
+                .collect();
+            for bridge in bridges.value {
+                let downstream = match bridge.downstream_pipeline {
+                    Some(pipeline) => {
+                        let url = format!(
+                            "{}/api/v4/projects/{}/pipelines/{}/jobs?include_retried=true",
+                            gitlab.config.server, pipeline.project_id, pipeline.id
+                        );
+                        let jobs = match gitlab.prepare_paged_request::<GitLabJob>(&url).await {
+                            Ok(jobs) => {
+                                modified |= jobs.modified;
+                                gitlab.map_jobs(&pipeline.project_id.to_string(), jobs.value)
+                            }
+                            // a project the token cannot read, the bridge still shows
+                            // status and link; other errors fail as they may be transient
+                            Err(error @ (Error::Auth { .. } | Error::Http { status: 404, .. })) => {
+                                console::log_1(&JsValue::from(format!(
+                                    "Jobs of downstream pipeline {} not available: {}",
+                                    pipeline.id, error
+                                )));
+                                vec![]
+                            }
+                            Err(error) => return Err(error),
+                        };
+                        Some(dom::Downstream {
+                            id: pipeline.id,
+                            status: map_status(&pipeline.status),
+                            r#ref: pipeline.r#ref,
+                            link: pipeline.web_url,
+                            jobs,
+                        })
+                    }
+                    None => None,
+                };
+                let job = dom::Job {
+                    name: bridge.name,
+                    stage: bridge.stage,
+                    status: map_status(&bridge.status),
+                    link: bridge.web_url,
+                    downstream,
+                    log: None,
+                    // trigger jobs are retried with their pipeline
+                    actions: vec![],
+                };
+                all.push((bridge.id, false, job));
+            }
+            Ok(Fetched {
+                value: order_by_stage(all),
+                modified,
+            })
+        }
+        .boxed_local()
+    }
+
+    fn request_merge_requests(
+        &self,
+        project: &dom::Project,
+        limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let url = format!(
+            "{}/api/v4/projects/{}/merge_requests",
+            self.config.server, project.id
+        );
+        let gitlab = self.clone();
+        async move {
+            let merge_requests = gitlab
+                .prepare_cached_request::<Vec<GitLabMergeRequest>>(&format!(
+                    "{}?state=opened&order_by=updated_at&sort=desc&per_page={}",
+                    url, limit
+                ))
+                .await?;
+            let mut modified = merge_requests.modified;
+
+            let mut pipelines = vec![];
+            for merge_request in merge_requests.value {
+                let merge_request_pipelines = gitlab
+                    .prepare_cached_request::<Vec<GitLabPipeline>>(&format!(
+                        "{}/{}/pipelines",
+                        url, merge_request.iid
                     ))
-                }))
+                    .await?;
+                modified |= merge_request_pipelines.modified;
+                // newest first, merge requests without a pipeline are not shown
+                if let Some(pipeline) = merge_request_pipelines.value.into_iter().next() {
+                    pipelines.push(dom::Pipeline {
+                        id: pipeline.id,
+                        status: map_status(&pipeline.status),
+                        r#ref: pipeline.r#ref,
+                        merge_request: Some(dom::MergeRequest {
+                            iid: merge_request.iid,
+                            title: merge_request.title,
+                            author: merge_request.author.name,
+                            target_branch: merge_request.target_branch,
+                            draft: merge_request.draft,
+                            link: merge_request.web_url,
+                        }),
+                    });
+                }
+            }
+            Ok(Fetched {
+                value: pipelines,
+                modified,
             })
-            .and_then(|jobs| {
-                future::ok(
-                    jobs.into_iter()
-                        .map(|j| dom::Job {
-                            name: j.name,
-                            status: map_status(&j.status),
-                            link: j.web_url,
-                        })
-                        .collect::<Vec<dom::Job>>(),
-                )
-            });
-        request_future
+        }
+        .boxed_local()
+    }
+
+    fn request_action(&self, action: &dom::Action) -> ProviderFuture<()> {
+        let url = format!(
+            "{}/api/v4/{}/{}",
+            self.config.server,
+            action.target,
+            action.kind.name()
+        );
+        let gitlab = self.clone();
+        async move {
+            let request = gitlab.client.new_post_request(&url, "")?;
+            gitlab
+                .client
+                .fetch_json::<IgnoredAny>(request)
+                .await
+                .map(|_| ())
+        }
+        .boxed_local()
+    }
+
+    fn request_log(&self, target: &str, lines: usize) -> ProviderFuture<Vec<String>> {
+        let url = format!("{}/api/v4/{}/trace", self.config.server, target);
+        let request = self.client.new_request(&url);
+        let client = self.client.clone();
+        async move {
+            let request = request?;
+            provider::set_header(&request, "Accept", "text/plain")?;
+            let trace = client.fetch_text(request).await?;
+            Ok(tail(&trace, lines))
+        }
+        .boxed_local()
     }
 }
 
-fn map_status(status: &str) -> dom::Status {
+impl GitLab {
+    /// Test counts of a pipeline, with the names of the failed tests if there are any.
+    /// None if the pipeline has no test reports.
+    async fn request_test_summary(
+        &self,
+        pipeline_url: &str,
+    ) -> Result<Option<dom::TestSummary>, Error> {
+        let summary = match self
+            .prepare_cached_request::<GitLabTestReportSummary>(&format!(
+                "{}/test_report_summary",
+                pipeline_url
+            ))
+            .await
+        {
+            Ok(summary) => summary.value.total,
+            // not available before GitLab 14.2, not worth logging
+            Err(Error::Http { status: 404, .. }) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        if summary.count == 0 {
+            return Ok(None);
+        }
+
+        let mut failing = vec![];
+        if summary.failed + summary.error > 0 {
+            let report = self
+                .prepare_cached_request::<GitLabTestReport>(&format!(
+                    "{}/test_report",
+                    pipeline_url
+                ))
+                .await?;
+            failing = report
+                .value
+                .test_suites
+                .into_iter()
+                .flat_map(|suite| suite.test_cases)
+                .filter(|case| case.status == "failed" || case.status == "error")
+                .take(MAX_FAILING_TESTS)
+                .map(|case| match case.classname {
+                    Some(classname) if !classname.is_empty() => {
+                        format!("{}.{}", classname, case.name)
+                    }
+                    _ => case.name,
+                })
+                .collect();
+        }
+        Ok(Some(dom::TestSummary {
+            total: summary.count,
+            failed: summary.failed,
+            skipped: summary.skipped,
+            error: summary.error,
+            failing,
+        }))
+    }
+
+    fn map_job(&self, project_id: &str, job: GitLabJob) -> dom::Job {
+        let status = map_status(&job.status);
+        let target = format!("projects/{}/jobs/{}", project_id, job.id);
+        dom::Job {
+            name: job.name,
+            stage: job.stage,
+            status,
+            link: job.web_url,
+            downstream: None,
+            log: (status == dom::Status::FAILED).then(|| target.clone()),
+            actions: self.actions(target, status, true),
+        }
+    }
+
+    fn map_jobs(&self, project_id: &str, jobs: Vec<GitLabJob>) -> Vec<dom::Job> {
+        order_by_stage(
+            jobs.into_iter()
+                .map(|job| (job.id, job.retried, self.map_job(project_id, job)))
+                .collect(),
+        )
+    }
+
+    /// Actions offered on a job or pipeline in `status`, none unless enabled for the source.
+    fn actions(&self, target: String, status: dom::Status, job: bool) -> Vec<dom::Action> {
+        if !self.config.actions {
+            return vec![];
+        }
+        let kind = match status {
+            dom::Status::FAILED | dom::Status::CANCELED => dom::ActionKind::Retry,
+            dom::Status::MANUAL if job => dom::ActionKind::Play,
+            dom::Status::CREATED | dom::Status::PENDING | dom::Status::RUNNING if job => {
+                dom::ActionKind::Cancel
+            }
+            _ => return vec![],
+        };
+        vec![dom::Action { kind, target }]
+    }
+}
+
+/// Jobs, given with their id and retried flag, grouped by stage in the order of the
+/// pipeline definition. Jobs are listed newest first; in creation order the oldest
+/// job of each stage gives its position, a retry creates a job with a higher id.
+/// Retried jobs only count for the order and are dropped.
+fn order_by_stage(mut jobs: Vec<(i64, bool, dom::Job)>) -> Vec<dom::Job> {
+    jobs.sort_by_key(|(id, _, _)| *id);
+    let mut stages: Vec<String> = vec![];
+    for (_, _, job) in &jobs {
+        if !stages.contains(&job.stage) {
+            stages.push(job.stage.clone());
+        }
+    }
+    jobs.retain(|(_, retried, _)| !retried);
+    // stable, jobs of a stage stay in creation order
+    jobs.sort_by_key(|(_, _, job)| stages.iter().position(|stage| *stage == job.stage));
+    jobs.into_iter().map(|(_, _, job)| job).collect()
+}
+
+/// The last `lines` lines of a job trace as displayed by GitLab: without ANSI
+/// escape codes and section markers, only the text after the last carriage
+/// return of a line (progress output overwrites itself).
+fn tail(trace: &str, lines: usize) -> Vec<String> {
+    let mut tail: Vec<String> = trace
+        .lines()
+        .filter_map(|line| {
+            let line = strip_ansi(line);
+            let mut markers = false;
+            let text = line
+                .split('\r')
+                .filter(|segment| {
+                    let marker = segment.starts_with("section_start:")
+                        || segment.starts_with("section_end:");
+                    markers |= marker;
+                    !marker
+                })
+                .last()
+                .unwrap_or_default();
+            // a line of section markers only is not displayed
+            (!markers || !text.is_empty()).then(|| text.to_string())
+        })
+        .collect();
+    while tail.last().is_some_and(|line| line.trim().is_empty()) {
+        tail.pop();
+    }
+    let start = tail.len().saturating_sub(lines);
+    tail.split_off(start)
+}
+
+/// Remove ANSI escape sequences, e.g. colors (`ESC [ 31 m`).
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // control sequence: parameters, then a final byte in `@`..=`~`
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // operating system command, terminated by BEL or `ESC \`
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Append a query parameter to an URL which may already have a query.
+fn with_query(url: &str, parameter: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, separator, parameter)
+}
+
+/// URL of the next page, from the `Link` header (keyset pagination) or
+/// the `X-Next-Page` header (offset pagination).
+fn next_page_url(url: &str, headers: &Headers) -> Option<String> {
+    if let Some(next) = provider::next_link(headers) {
+        return Some(next);
+    }
+    match headers.get("X-Next-Page") {
+        Ok(Some(page)) if !page.is_empty() => {
+            let base = url
+                .split('&')
+                .filter(|parameter| !parameter.starts_with("page="))
+                .collect::<Vec<_>>()
+                .join("&");
+            Some(with_query(&base, &format!("page={}", page)))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn map_status(status: &str) -> dom::Status {
     match status {
-        "created" => dom::Status::CREATED,
-        "pending" => dom::Status::PENDING,
+        "created" | "scheduled" => dom::Status::CREATED,
+        "pending" | "waiting_for_resource" | "preparing" => dom::Status::PENDING,
         "running" => dom::Status::RUNNING,
         "failed" => dom::Status::FAILED,
         "success" => dom::Status::SUCCESS,
@@ -236,3 +685,78 @@ fn map_status(status: &str) -> dom::Status {
         _ => dom::Status::FAILED, // TODO unknown status
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str, stage: &str) -> dom::Job {
+        dom::Job {
+            name: name.to_string(),
+            stage: stage.to_string(),
+            status: dom::Status::SUCCESS,
+            link: String::new(),
+            downstream: None,
+            log: None,
+            actions: vec![],
+        }
+    }
+
+    fn names(jobs: &[dom::Job]) -> Vec<&str> {
+        jobs.iter().map(|job| job.name.as_str()).collect()
+    }
+
+    #[test]
+    fn stages_ordered_by_oldest_job() {
+        let jobs = vec![
+            (4, false, job("deploy", "deploy")),
+            (3, false, job("unit", "test")),
+            (2, false, job("lint", "build")),
+            (1, false, job("compile", "build")),
+        ];
+        assert_eq!(
+            names(&order_by_stage(jobs)),
+            ["compile", "lint", "unit", "deploy"]
+        );
+    }
+
+    #[test]
+    fn retried_job_keeps_its_stage_first() {
+        let jobs = vec![
+            (5, false, job("compile", "build")),
+            (3, false, job("deploy", "deploy")),
+            (2, false, job("unit", "test")),
+            (1, true, job("compile", "build")),
+        ];
+        let ordered = order_by_stage(jobs);
+        assert_eq!(names(&ordered), ["compile", "unit", "deploy"]);
+        assert_eq!(ordered[0].stage, "build");
+    }
+
+    #[test]
+    fn colour_codes_stripped() {
+        assert_eq!(
+            strip_ansi("\u{1b}[31;1mERROR:\u{1b}[0;m Job failed\u{1b}[0K"),
+            "ERROR: Job failed"
+        );
+        assert_eq!(
+            strip_ansi("\u{1b}]8;;https://example.com\u{7}link\u{1b}]8;;\u{1b}\\ text"),
+            "link text"
+        );
+    }
+
+    #[test]
+    fn section_markers_dropped() {
+        let trace = "section_start:1700000000:step_script[collapsed=true]\r\u{1b}[0K\u{1b}[36;1mExecuting step\u{1b}[0;m\n\
+                     $ make test\n\
+                     section_end:1700000001:step_script\r\u{1b}[0K\n";
+        assert_eq!(tail(trace, 10), ["Executing step", "$ make test"]);
+    }
+
+    #[test]
+    fn progress_output_shows_last_state() {
+        let trace = "Downloading 10%\rDownloading 50%\rDownloading 100%\nERROR: Job failed\n\n";
+        assert_eq!(tail(trace, 10), ["Downloading 100%", "ERROR: Job failed"]);
+        assert_eq!(tail(trace, 1), ["ERROR: Job failed"]);
+    }
+}
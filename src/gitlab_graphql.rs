@@ -0,0 +1,423 @@
+use crate::config::SourceConfig;
+use crate::dom;
+use crate::error::Error;
+use crate::gitlab;
+use crate::provider::{self, CiProvider, Fetched, ProviderFuture};
+use futures::future::{self, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Projects per page, kept low as every project carries its pipelines and jobs.
+const PROJECTS_PER_PAGE: u32 = 10;
+
+const QUERY: &str = r#"
+query($after: String, $projects: Int, $pipelines: Int, $ref: String, $scope: PipelineScopeEnum, $source: String) {
+  projects(membership: true, first: $projects, after: $after) {
+    pageInfo { hasNextPage endCursor }
+    nodes {
+      id
+      name
+      archived
+      namespace { name }
+      repository { rootRef }
+      pipelines(first: $pipelines, ref: $ref, scope: $scope, source: $source) {
+        nodes {
+          id
+          status
+          ref
+          duration
+          stages {
+            nodes {
+              name
+              jobs {
+                nodes {
+                  name status webPath
+                  downstreamPipeline {
+                    id status ref path
+                    jobs { nodes { name status webPath stage { name } } }
+                  }
+                }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(Serialize)]
+struct Query<'a> {
+    query: &'a str,
+    variables: Variables<'a>,
+}
+
+#[derive(Serialize)]
+struct Variables<'a> {
+    after: Option<String>,
+    projects: u32,
+    pipelines: u32,
+    r#ref: Option<&'a str>,
+    scope: Option<&'a str>,
+    source: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Option<Data>,
+    #[serde(default)]
+    errors: Vec<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    projects: Connection<GqlProject>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection<T> {
+    #[serde(default)]
+    page_info: Option<PageInfo>,
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GqlNamespace {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlRepository {
+    root_ref: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GqlProject {
+    id: String,
+    name: String,
+    #[serde(default)]
+    archived: bool,
+    namespace: Option<GqlNamespace>,
+    repository: Option<GqlRepository>,
+    pipelines: Option<Connection<GqlPipeline>>,
+}
+
+#[derive(Deserialize)]
+struct GqlPipeline {
+    id: String,
+    status: String,
+    r#ref: Option<String>,
+    duration: Option<i32>,
+    stages: Option<Connection<GqlStage>>,
+}
+
+#[derive(Deserialize)]
+struct GqlStage {
+    name: Option<String>,
+    jobs: Option<Connection<GqlJob>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlJob {
+    name: Option<String>,
+    status: Option<String>,
+    web_path: Option<String>,
+    /// Stage of a downstream pipeline's job, in the query nested below the stage otherwise.
+    stage: Option<GqlStageName>,
+    downstream_pipeline: Option<GqlDownstreamPipeline>,
+}
+
+#[derive(Deserialize)]
+struct GqlStageName {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GqlDownstreamPipeline {
+    id: String,
+    status: String,
+    r#ref: Option<String>,
+    path: Option<String>,
+    jobs: Option<Connection<GqlJob>>,
+}
+
+/// A pipeline of the latest query result.
+struct Pipeline {
+    detail: dom::PipelineDetail,
+    jobs: Vec<dom::Job>,
+}
+
+/// GitLab through its GraphQL API: `request_projects` queries all projects
+/// with their pipelines and jobs, the other requests are answered from that result.
+pub struct GitLabGraphQl {
+    config: Arc<SourceConfig>,
+    client: provider::Client,
+    /// Latest pipelines queried per project, ref filters of a single project are applied to these.
+    max_pipelines: usize,
+    /// Pipelines of the latest query by project id, newest first.
+    pipelines: Rc<RefCell<HashMap<String, Vec<Pipeline>>>>,
+}
+
+impl GitLabGraphQl {
+    pub fn new(config: Arc<SourceConfig>, client: provider::Client, max_pipelines: usize) -> Self {
+        let client = client.with_header("Authorization", format!("Bearer {}", config.token));
+        GitLabGraphQl {
+            config,
+            client,
+            max_pipelines,
+            pipelines: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("{}/api/graphql", self.config.server)
+    }
+
+    /// Error for a project or pipeline missing from the query result.
+    fn missing(&self, what: String) -> Error {
+        Error::decode(&self.url(), format!("{} not in query result", what))
+    }
+}
+
+impl CiProvider for GitLabGraphQl {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>> {
+        let config = self.config.clone();
+        let client = self.client.clone();
+        let store = self.pipelines.clone();
+        let url = self.url();
+        let max_pipelines = self.max_pipelines as u32;
+        async move {
+            let refs = config.refs.clone().unwrap_or_default();
+            let single_ref = match refs.refs.as_slice() {
+                [name] if !refs.default_branch && !name.contains(['*', '?']) => Some(name.as_str()),
+                _ => None,
+            };
+
+            let mut projects = vec![];
+            let mut after = None;
+            let mut pages = 0;
+            loop {
+                let query = Query {
+                    query: QUERY,
+                    variables: Variables {
+                        after: after.take(),
+                        projects: PROJECTS_PER_PAGE,
+                        pipelines: max_pipelines,
+                        r#ref: single_ref,
+                        scope: Some("TAGS").filter(|_| refs.tags),
+                        source: Some("merge_request_event").filter(|_| refs.merge_requests),
+                    },
+                };
+                let body =
+                    serde_json::to_string(&query).map_err(|e| Error::Config(e.to_string()))?;
+                let request = client.new_post_request(&url, &body)?;
+                let response: Response = client.fetch_json(request).await?;
+                if !response.errors.is_empty() {
+                    let messages: Vec<String> =
+                        response.errors.into_iter().map(|e| e.message).collect();
+                    return Err(Error::Api {
+                        url,
+                        message: messages.join(", "),
+                    });
+                }
+                let page = response
+                    .data
+                    .ok_or_else(|| Error::decode(&url, "response without data"))?
+                    .projects;
+                projects.extend(page.nodes);
+                pages += 1;
+                match page.page_info {
+                    Some(info) if info.has_next_page && pages < config.max_pages => {
+                        after = info.end_cursor;
+                    }
+                    _ => break,
+                }
+            }
+
+            let mut store = store.borrow_mut();
+            store.clear();
+            let projects = projects
+                .into_iter()
+                .filter(|p| {
+                    config
+                        .filter
+                        .archived
+                        .is_none_or(|archived| p.archived == archived)
+                })
+                .map(|p| {
+                    let id = global_id(&p.id).to_string();
+                    let pipelines = p
+                        .pipelines
+                        .map(|connection| connection.nodes)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|pipeline| map_pipeline(&config.server, pipeline))
+                        .collect();
+                    store.insert(id.clone(), pipelines);
+                    dom::Project {
+                        id,
+                        name: p.name,
+                        group: p.namespace.map(|n| n.name).unwrap_or_default(),
+                        default_branch: p.repository.and_then(|r| r.root_ref),
+                    }
+                })
+                .collect();
+            Ok(projects)
+        }
+        .boxed_local()
+    }
+
+    fn request_pipelines(
+        &self,
+        project: &dom::Project,
+        _limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let result = match self.pipelines.borrow().get(&project.id) {
+            Some(pipelines) => Ok(Fetched::new(
+                pipelines
+                    .iter()
+                    .map(|p| dom::Pipeline {
+                        id: p.detail.id,
+                        status: p.detail.status,
+                        r#ref: p.detail.r#ref.clone(),
+                        merge_request: None,
+                    })
+                    .collect(),
+            )),
+            None => Err(self.missing(format!("project {}", project.id))),
+        };
+        future::ready(result).boxed_local()
+    }
+
+    fn request_pipeline_detail(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail> {
+        let result = self
+            .find(project_id, pipeline_id, |p| p.detail.clone())
+            .ok_or_else(|| self.missing(format!("pipeline {}", pipeline_id)));
+        future::ready(result).boxed_local()
+    }
+
+    fn request_jobs(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>> {
+        let result = self
+            .find(project_id, pipeline_id, |p| Fetched::new(p.jobs.clone()))
+            .ok_or_else(|| self.missing(format!("pipeline {}", pipeline_id)));
+        future::ready(result).boxed_local()
+    }
+}
+
+impl GitLabGraphQl {
+    fn find<T>(&self, project_id: &str, pipeline_id: i64, f: impl Fn(&Pipeline) -> T) -> Option<T> {
+        self.pipelines
+            .borrow()
+            .get(project_id)?
+            .iter()
+            .find(|p| p.detail.id == pipeline_id)
+            .map(f)
+    }
+}
+
+fn map_pipeline(server: &str, pipeline: GqlPipeline) -> Option<Pipeline> {
+    let id = global_id(&pipeline.id).parse().ok()?;
+    let jobs = pipeline
+        .stages
+        .map(|stages| stages.nodes)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|stage| {
+            let name = stage.name.unwrap_or_default();
+            stage
+                .jobs
+                .map(|jobs| jobs.nodes)
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |job| (name.clone(), job))
+        })
+        .map(|(stage, job)| map_job(server, stage, job))
+        .collect();
+    Some(Pipeline {
+        detail: dom::PipelineDetail {
+            id,
+            status: map_status(&pipeline.status),
+            r#ref: pipeline.r#ref.unwrap_or_default(),
+            duration: pipeline.duration.unwrap_or(0),
+            actions: vec![],
+            tests: None,
+        },
+        jobs,
+    })
+}
+
+fn map_job(server: &str, stage: String, job: GqlJob) -> dom::Job {
+    let downstream = job.downstream_pipeline.and_then(|pipeline| {
+        let jobs = pipeline
+            .jobs
+            .map(|jobs| jobs.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut job| {
+                let stage = job.stage.take().and_then(|s| s.name).unwrap_or_default();
+                // only one level of downstream pipelines is queried
+                job.downstream_pipeline = None;
+                map_job(server, stage, job)
+            })
+            .collect();
+        Some(dom::Downstream {
+            id: global_id(&pipeline.id).parse().ok()?,
+            status: map_status(&pipeline.status),
+            r#ref: pipeline.r#ref.unwrap_or_default(),
+            link: link(server, pipeline.path),
+            jobs,
+        })
+    });
+    dom::Job {
+        name: job.name.unwrap_or_default(),
+        stage,
+        status: map_status(job.status.as_deref().unwrap_or_default()),
+        link: link(server, job.web_path),
+        downstream,
+        log: None,
+        actions: vec![],
+    }
+}
+
+/// Absolute URL of a path relative to the server.
+fn link(server: &str, path: Option<String>) -> String {
+    path.map(|path| format!("{}{}", server, path))
+        .unwrap_or_default()
+}
+
+/// Numeric id of a global id like `gid://gitlab/Project/42`, the same as in the REST API.
+fn global_id(id: &str) -> &str {
+    id.rsplit('/').next().unwrap_or(id)
+}
+
+/// GraphQL statuses are the upper case REST statuses.
+fn map_status(status: &str) -> dom::Status {
+    gitlab::map_status(&status.to_ascii_lowercase())
+}
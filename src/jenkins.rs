@@ -0,0 +1,275 @@
+use crate::config::SourceConfig;
+use crate::dom;
+use crate::error::Error;
+use crate::provider::{self, CiProvider, Fetched, ProviderFuture};
+use futures::{FutureExt, TryFutureExt};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const REVISION_TREE: &str = "actions[lastBuiltRevision[branch[name]]]";
+const JOB_TREE: &str = "name,fullName,jobs[name,fullName,jobs[name,fullName,jobs[name,fullName]]]";
+
+#[derive(Deserialize)]
+struct JenkinsStage {
+    name: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct JenkinsRun {
+    stages: Vec<JenkinsStage>,
+}
+
+#[derive(Deserialize)]
+struct JenkinsBranch {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JenkinsRevision {
+    branch: Vec<JenkinsBranch>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JenkinsAction {
+    last_built_revision: Option<JenkinsRevision>,
+}
+
+#[derive(Deserialize)]
+struct JenkinsBuildDetail {
+    number: i64,
+    result: Option<String>,
+    building: bool,
+    duration: i64,
+    #[serde(default)]
+    actions: Vec<Option<JenkinsAction>>,
+}
+
+#[derive(Deserialize)]
+struct JenkinsBuild {
+    number: i64,
+    result: Option<String>,
+    building: bool,
+    #[serde(default)]
+    actions: Vec<Option<JenkinsAction>>,
+}
+
+#[derive(Deserialize)]
+struct JenkinsBuilds {
+    builds: Vec<JenkinsBuild>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JenkinsJob {
+    name: String,
+    full_name: String,
+    jobs: Option<Vec<JenkinsJob>>,
+}
+
+#[derive(Deserialize)]
+struct JenkinsJobs {
+    jobs: Vec<JenkinsJob>,
+}
+
+pub struct Jenkins {
+    config: Arc<SourceConfig>,
+    client: provider::Client,
+}
+
+impl Jenkins {
+    pub fn new(config: Arc<SourceConfig>, client: provider::Client) -> Result<Self, Error> {
+        let client = match &config.user {
+            Some(user) => {
+                let window = web_sys::window()
+                    .ok_or_else(|| Error::Dom("no global `window` exists".into()))?;
+                let credentials = window
+                    .btoa(&format!("{}:{}", user, config.token))
+                    .map_err(|_| Error::Config("Credentials must be Latin-1".into()))?;
+                client.with_header("Authorization", format!("Basic {}", credentials))
+            }
+            None => client,
+        };
+        Ok(Jenkins { config, client })
+    }
+
+    /// URL of a job given by its full name, each folder level is a `/job/<name>` segment.
+    fn job_url(&self, full_name: &str) -> String {
+        full_name
+            .split('/')
+            .fold(self.config.server.clone(), |url, segment| {
+                format!(
+                    "{}/job/{}",
+                    url,
+                    String::from(js_sys::encode_uri_component(segment))
+                )
+            })
+    }
+}
+
+impl CiProvider for Jenkins {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>> {
+        let url = format!("{}/api/json?tree=jobs[{}]", self.config.server, JOB_TREE);
+        self.client
+            .prepare_request::<JenkinsJobs>(&url)
+            .map_ok(|root| {
+                let mut projects = vec![];
+                collect_projects(root.jobs, &mut projects);
+                projects
+            })
+            .boxed_local()
+    }
+
+    fn request_pipelines(
+        &self,
+        project: &dom::Project,
+        _limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let url = format!(
+            "{}/api/json?tree=builds[number,result,building,{}]",
+            self.job_url(&project.id),
+            REVISION_TREE
+        );
+        self.client
+            .prepare_request::<JenkinsBuilds>(&url)
+            .map_ok(|builds| {
+                builds
+                    .builds
+                    .into_iter()
+                    .map(|b| dom::Pipeline {
+                        id: b.number,
+                        status: map_status(b.result.as_deref(), b.building),
+                        r#ref: built_branch(&b.actions),
+                        merge_request: None,
+                    })
+                    .collect()
+            })
+            .map_ok(Fetched::new)
+            .boxed_local()
+    }
+
+    fn request_pipeline_detail(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail> {
+        let url = format!(
+            "{}/{}/api/json?tree=number,result,building,duration,{}",
+            self.job_url(project_id),
+            pipeline_id,
+            REVISION_TREE
+        );
+        self.client
+            .prepare_request::<JenkinsBuildDetail>(&url)
+            .map_ok(|build| dom::PipelineDetail {
+                id: build.number,
+                status: map_status(build.result.as_deref(), build.building),
+                r#ref: built_branch(&build.actions),
+                duration: if build.building {
+                    0
+                } else {
+                    (build.duration / 1000) as i32
+                },
+                actions: vec![],
+                tests: None,
+            })
+            .boxed_local()
+    }
+
+    fn request_jobs(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>> {
+        let build_url = format!("{}/{}", self.job_url(project_id), pipeline_id);
+        let url = format!("{}/wfapi/describe", build_url);
+        let run = self.client.prepare_request::<JenkinsRun>(&url);
+        async move {
+            let stages = match run.await {
+                Ok(run) => run.stages,
+                // only pipeline runs have stages, not freestyle builds
+                Err(Error::Http { status: 404, .. }) => vec![],
+                Err(error) => return Err(error),
+            };
+            Ok(Fetched::new(
+                stages
+                    .into_iter()
+                    .map(|s| dom::Job {
+                        name: s.name,
+                        stage: String::new(),
+                        downstream: None,
+                        status: map_stage_status(&s.status),
+                        link: format!("{}/console", build_url),
+                        log: None,
+                        actions: vec![],
+                    })
+                    .collect(),
+            ))
+        }
+        .boxed_local()
+    }
+}
+
+/// Flatten folders (and multibranch projects) into their buildable jobs.
+fn collect_projects(jobs: Vec<JenkinsJob>, projects: &mut Vec<dom::Project>) {
+    for job in jobs {
+        match job.jobs {
+            Some(children) => collect_projects(children, projects),
+            None => {
+                let group = match job.full_name.rfind('/') {
+                    Some(index) => job.full_name[..index].to_string(),
+                    None => String::new(),
+                };
+                projects.push(dom::Project {
+                    id: job.full_name,
+                    name: job.name,
+                    group,
+                    default_branch: None,
+                });
+            }
+        }
+    }
+}
+
+/// Branch built by the git plugin, empty if unknown.
+fn built_branch(actions: &[Option<JenkinsAction>]) -> String {
+    actions
+        .iter()
+        .flatten()
+        .filter_map(|a| a.last_built_revision.as_ref())
+        .flat_map(|r| r.branch.iter())
+        .map(|b| b.name.clone())
+        .next()
+        .unwrap_or_default()
+}
+
+fn map_status(result: Option<&str>, building: bool) -> dom::Status {
+    if building {
+        return dom::Status::RUNNING;
+    }
+    match result {
+        Some("SUCCESS") => dom::Status::SUCCESS,
+        Some("FAILURE") | Some("UNSTABLE") => dom::Status::FAILED,
+        Some("ABORTED") => dom::Status::CANCELED,
+        Some("NOT_BUILT") => dom::Status::SKIPPED,
+        None => dom::Status::PENDING,
+        // the build is finished, but its result unknown
+        _ => dom::Status::SKIPPED,
+    }
+}
+
+fn map_stage_status(status: &str) -> dom::Status {
+    match status {
+        "SUCCESS" => dom::Status::SUCCESS,
+        "FAILED" | "UNSTABLE" => dom::Status::FAILED,
+        "ABORTED" => dom::Status::CANCELED,
+        "NOT_EXECUTED" => dom::Status::SKIPPED,
+        "IN_PROGRESS" => dom::Status::RUNNING,
+        "PAUSED_PENDING_INPUT" => dom::Status::MANUAL,
+        "QUEUED" => dom::Status::PENDING,
+        // neutral and not finished, the stage is requested again
+        _ => dom::Status::PENDING,
+    }
+}
@@ -2,22 +2,33 @@ extern crate cfg_if;
 extern crate wasm_bindgen;
 
 use cfg_if::cfg_if;
+use config::{Config, GitLabApi, Limits, ProviderKind, SourceConfig, View};
+use error::Error;
 use futures::future;
-use futures::TryFutureExt;
-use serde::{Deserialize, Serialize};
+use futures::stream::{self, StreamExt};
+use provider::{Cache, CiProvider, Throttle};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use std::sync::Arc;
-use std::sync::Mutex;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use wasm_bindgen_futures::future_to_promise;
-use web_sys::console;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{console, AbortController, AbortSignal, Element};
 
-mod dom;
+mod config;
+pub mod dom;
+mod error;
+mod filter;
+mod gitea;
+mod github;
 mod gitlab;
+mod gitlab_graphql;
+mod jenkins;
+mod provider;
 mod utils;
 
-const REFRESH_INTERVAL: i32 = 60000;
-
 cfg_if! {
     // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
     // allocator.
@@ -33,139 +44,679 @@ extern "C" {
     fn alert(s: &str);
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct Config {
-    server: String,
-    token: String,
+struct State {
+    projects: HashMap<usize, Vec<dom::Project>>,
+    sources: HashMap<usize, dom::SourceStatus>,
+    /// Aborts the requests of the refresh cycle in flight, per source.
+    cycles: HashMap<usize, AbortController>,
+    throttles: HashMap<usize, Rc<Throttle>>,
+    caches: HashMap<usize, Rc<Cache>>,
+    /// Finished pipelines by project key and pipeline id, not requested again.
+    finished: HashMap<String, HashMap<i64, FinishedPipeline>>,
 }
 
-struct State {
-    projects: Vec<dom::Project>,
+/// A pipeline which will not change anymore, unless it is retried.
+struct FinishedPipeline {
+    detail: dom::PipelineDetail,
+    jobs: Vec<dom::Job>,
 }
 
 impl State {
     fn new() -> Self {
-        State { projects: vec![] }
+        State {
+            projects: HashMap::new(),
+            sources: HashMap::new(),
+            cycles: HashMap::new(),
+            throttles: HashMap::new(),
+            caches: HashMap::new(),
+            finished: HashMap::new(),
+        }
+    }
+
+    fn set_projects(&mut self, source: usize, projects: Vec<dom::Project>) {
+        let prefix = dom::Dom::project_key(source, "");
+        let keep: HashSet<String> = projects
+            .iter()
+            .map(|p| dom::Dom::project_key(source, &p.id))
+            .collect();
+        self.finished.retain(|project_key, _| {
+            !project_key.starts_with(&prefix) || keep.contains(project_key)
+        });
+        self.projects.insert(source, projects);
+    }
+
+    fn source_status(&mut self, source: usize, label: &str) -> &mut dom::SourceStatus {
+        self.sources
+            .entry(source)
+            .or_insert_with(|| dom::SourceStatus::new(label))
+    }
+}
+
+type AppState = Rc<RefCell<State>>;
+
+thread_local! {
+    static APP: RefCell<Option<App>> = const { RefCell::new(None) };
+}
+
+/// The running dashboard, kept to reschedule the refresh at runtime.
+struct App {
+    config: Config,
+    state: AppState,
+    sources: Vec<Arc<SourceConfig>>,
+    timers: Vec<Timer>,
+    _actions: Listener,
+}
+
+impl App {
+    /// (Re)start the refresh timer of every source with its current interval.
+    fn schedule(&mut self) -> Result<(), Error> {
+        let window =
+            web_sys::window().ok_or_else(|| Error::Dom("no global `window` exists".into()))?;
+        self.timers.clear();
+
+        for (index, source) in self.sources.iter().enumerate() {
+            let state = self.state.clone();
+            let source = source.clone();
+            let limits = self.config.limits(&source);
+            let interval = limits.refresh_interval;
+            let x = Box::new(move || {
+                if let Err(error) = update(state.clone(), index, source.clone(), limits) {
+                    console::log_1(&JsValue::from(error.to_string()));
+                }
+            }) as Box<dyn Fn()>;
+
+            let refresh = Closure::wrap(x);
+            let handle = window
+                .set_interval_with_callback_and_timeout_and_arguments_0(
+                    refresh.as_ref().unchecked_ref(),
+                    (i64::from(interval) * 1000).min(i64::from(i32::MAX)) as i32,
+                )
+                .map_err(Error::dom)?;
+            self.timers.push(Timer {
+                handle,
+                _callback: refresh,
+            });
+        }
+        Ok(())
     }
+}
 
-    fn set_projects(&mut self, projects: Vec<dom::Project>) {
-        self.projects = projects;
+/// A click listener on the content, removed when dropped.
+struct Listener {
+    target: Element,
+    callback: Closure<dyn Fn(web_sys::Event)>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self
+            .target
+            .remove_event_listener_with_callback("click", self.callback.as_ref().unchecked_ref());
     }
 }
 
-type AppState = Arc<Mutex<State>>;
+/// An interval timer, cleared when dropped.
+struct Timer {
+    handle: i32,
+    _callback: Closure<dyn Fn()>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(window) = web_sys::window() {
+            window.clear_interval_with_handle(self.handle);
+        }
+    }
+}
 
 #[wasm_bindgen]
 pub fn run(config: JsValue) -> Result<(), JsValue> {
     utils::set_panic_hook();
 
-    let state: AppState = Arc::new(Mutex::new(State::new()));
-    let window = web_sys::window().expect("no global `window` exists");
+    let config = Config::parse(&config)?;
+    let state: AppState = Rc::new(RefCell::new(State::new()));
+    let sources: Vec<Arc<SourceConfig>> = config.sources.iter().cloned().map(Arc::new).collect();
 
-    {
-        let state = state.clone();
-        let config = config.clone();
-        let x = Box::new(move || {
-            let state = state.clone();
-            update(&config, state).unwrap_or({
-                console::log_1(&JsValue::from("Failed to update"));
-            })
-        }) as Box<dyn Fn()>;
+    let document = document()?;
+    for (index, source) in sources.iter().enumerate() {
+        // render the status lines upfront to keep them in config order
+        dom::Dom::update_source_status(&document, index, &dom::SourceStatus::new(source.label()))?;
+    }
 
-        let refresh = Closure::wrap(x);
+    for (index, source) in sources.iter().enumerate() {
+        update(state.clone(), index, source.clone(), config.limits(source))?;
+    }
 
-        window.set_interval_with_callback_and_timeout_and_arguments_0(
-            refresh.as_ref().unchecked_ref(),
-            REFRESH_INTERVAL,
-        )?;
-        refresh.forget();
+    let content = document
+        .get_element_by_id("Content")
+        .ok_or_else(|| Error::Dom("document should have content region".into()))?;
+    let callback = Closure::wrap(Box::new(on_click) as Box<dyn Fn(web_sys::Event)>);
+    content
+        .add_event_listener_with_callback("click", callback.as_ref().unchecked_ref())
+        .map_err(Error::dom)?;
+
+    let mut app = App {
+        config,
+        state,
+        sources,
+        timers: vec![],
+        _actions: Listener {
+            target: content,
+            callback,
+        },
+    };
+    app.schedule()?;
+    APP.with(|current| current.replace(Some(app)));
+    Ok(())
+}
+
+/// Change the refresh interval (in seconds) of all sources without their own interval.
+#[wasm_bindgen]
+pub fn set_refresh_interval(seconds: u32) -> Result<(), JsValue> {
+    if seconds < config::MIN_REFRESH_INTERVAL {
+        return Err(JsValue::from(format!(
+            "Refresh interval must be at least {}s",
+            config::MIN_REFRESH_INTERVAL
+        )));
     }
+    APP.with(|current| match current.borrow_mut().as_mut() {
+        Some(app) => {
+            app.config.refresh_interval = seconds;
+            Ok(app.schedule()?)
+        }
+        None => Err(JsValue::from("Dashboard is not running")),
+    })
+}
 
-    // console::log_1(&config);
-    update(&config, state)
+/// Conditional request cache statistics per source, for debugging.
+#[wasm_bindgen]
+pub fn cache_stats() -> Result<JsValue, JsValue> {
+    APP.with(|current| match current.borrow().as_ref() {
+        Some(app) => {
+            let state = app.state.borrow();
+            let stats: Vec<SourceCacheStats> = app
+                .sources
+                .iter()
+                .enumerate()
+                .map(|(index, source)| SourceCacheStats {
+                    source: source.label().to_string(),
+                    stats: state
+                        .caches
+                        .get(&index)
+                        .map(|cache| cache.stats())
+                        .unwrap_or_default(),
+                })
+                .collect();
+            #[allow(deprecated)]
+            let stats = JsValue::from_serde(&stats);
+            stats.map_err(|e| JsValue::from(e.to_string()))
+        }
+        None => Err(JsValue::from("Dashboard is not running")),
+    })
 }
 
-fn update(config: &JsValue, state: AppState) -> Result<(), JsValue> {
-    let config: Arc<Config> = Arc::new(config.into_serde().expect("Failed to parse config"));
+#[derive(Serialize)]
+struct SourceCacheStats {
+    source: String,
+    #[serde(flatten)]
+    stats: provider::CacheStats,
+}
 
-    let window = web_sys::window().expect("no global `window` exists");
-    let _origin = window.location().origin()?;
+fn update(
+    state: AppState,
+    index: usize,
+    source: Arc<SourceConfig>,
+    limits: Limits,
+) -> Result<(), Error> {
+    let document = document()?;
 
-    let document = Arc::new(window.document().expect("should have a document on window"));
+    let controller = AbortController::new().map_err(Error::dom)?;
+    let (throttle, cache, provider) = {
+        let mut state = state.borrow_mut();
+        let throttle = state.throttles.entry(index).or_default().clone();
+        let cache = state.caches.entry(index).or_default().clone();
+        let client = provider::Client::new(controller.signal(), throttle.clone(), cache.clone());
+        let provider = new_provider(&source, client, limits.max_pipelines)?;
+        let status = state.source_status(index, source.label());
+        if let Some(until) = throttle.paused_until() {
+            status.throttled_until = Some(until);
+            dom::Dom::update_source_status(&document, index, status)?;
+            return Ok(());
+        }
+        if let Some(since) = status.refreshing_since {
+            // a cycle hanging for longer than two intervals is given up
+            let timeout = f64::from(limits.refresh_interval) * 2000.0;
+            if js_sys::Date::now() - since < timeout {
+                console::log_1(&JsValue::from(format!(
+                    "Refresh of {} still running, skipped",
+                    source.label()
+                )));
+                return Ok(());
+            }
+            console::log_1(&JsValue::from(format!(
+                "Refresh of {} timed out, aborted",
+                source.label()
+            )));
+        }
+        status.refreshing_since = Some(js_sys::Date::now());
+        dom::Dom::update_source_status(&document, index, status)?;
+        if let Some(previous) = state.cycles.insert(index, controller.clone()) {
+            previous.abort();
+        }
+        (throttle, cache, provider)
+    };
 
-    update_gitlab(document.clone(), state.clone(), config.clone());
+    let signal = controller.signal();
+    let refresh = Refresh {
+        document,
+        state,
+        index,
+        source,
+        provider,
+        signal,
+        throttle,
+        cache,
+        max_pipelines: limits.max_pipelines,
+        max_requests: limits.max_requests,
+    };
+    spawn_local(async move {
+        let result = refresh.run().await;
+        match &result {
+            // responses of failed projects are discarded already
+            Ok(_) if !refresh.signal.aborted() => refresh.cache.commit(),
+            _ => refresh.cache.discard(),
+        }
+        // an aborted cycle has been replaced, the new one reports
+        if !refresh.signal.aborted() {
+            refresh.report(result);
+        }
+    });
 
     Ok(())
 }
 
-fn update_gitlab(document: Arc<web_sys::Document>, state: AppState, config: Arc<Config>) {
-    let gitlab = Arc::new(gitlab::GitLab::new(config));
-    let future = gitlab.request_projects().and_then(move |projects| {
-        let guard = state.lock();
-        match guard {
-            Ok(mut state) => state.set_projects(projects.clone()),
-            Err(err) => console::log_1(&JsValue::from(format!("Failed to store state {}", err))),
+fn new_provider(
+    source: &Arc<SourceConfig>,
+    client: provider::Client,
+    max_pipelines: usize,
+) -> Result<Rc<dyn CiProvider>, Error> {
+    match source.provider {
+        ProviderKind::GitLab => match source.api {
+            GitLabApi::Rest => Ok(Rc::new(gitlab::GitLab::new(source.clone(), client))),
+            GitLabApi::GraphQl => Ok(Rc::new(gitlab_graphql::GitLabGraphQl::new(
+                source.clone(),
+                client,
+                max_pipelines,
+            ))),
+        },
+        ProviderKind::GitHub => Ok(Rc::new(github::GitHub::new(source.clone(), client))),
+        ProviderKind::Jenkins => Ok(Rc::new(jenkins::Jenkins::new(source.clone(), client)?)),
+        ProviderKind::Gitea => Ok(Rc::new(gitea::Gitea::new(source.clone(), client))),
+    }
+}
+
+/// Handle clicks on the buttons of jobs and pipelines, delegated from `#Content`.
+fn on_click(event: web_sys::Event) {
+    let button = event
+        .target()
+        .and_then(|target| target.dyn_into::<Element>().ok())
+        .and_then(|element| element.closest("button").ok().flatten());
+    let Some(button) = button else {
+        return;
+    };
+    let result = match button.class_name().as_str() {
+        "action" => start_action(&button),
+        "log-toggle" => toggle_log(&button),
+        _ => return,
+    };
+    // the button may be inside a job link
+    event.prevent_default();
+    event.stop_propagation();
+    if let Err(error) = result {
+        console::log_1(&JsValue::from(error.to_string()));
+    }
+}
+
+/// The source of the project a clicked button belongs to.
+struct ButtonSource {
+    state: AppState,
+    index: usize,
+    source: Arc<SourceConfig>,
+    limits: Limits,
+    log_lines: usize,
+    project_key: String,
+}
+
+impl ButtonSource {
+    fn of(button: &Element) -> Result<Self, Error> {
+        let project_key = button
+            .closest(".project")
+            .map_err(Error::dom)?
+            .map(|project| project.id())
+            .ok_or_else(|| Error::Dom("button outside of a project".into()))?;
+        APP.with(|current| {
+            let current = current.borrow();
+            let app = current.as_ref()?;
+            let index = app
+                .state
+                .borrow()
+                .projects
+                .iter()
+                .find_map(|(index, projects)| {
+                    projects
+                        .iter()
+                        .any(|p| dom::Dom::project_key(*index, &p.id) == project_key)
+                        .then_some(*index)
+                })?;
+            let source = app.sources.get(index)?.clone();
+            Some(ButtonSource {
+                state: app.state.clone(),
+                index,
+                limits: app.config.limits(&source),
+                log_lines: app.config.log_lines,
+                source,
+                project_key: project_key.clone(),
+            })
+        })
+        .ok_or_else(|| Error::Dom(format!("no source for project {}", project_key)))
+    }
+
+    /// A provider for requests outside of the refresh cycles, not aborted by them.
+    fn provider(&self) -> Result<Rc<dyn CiProvider>, Error> {
+        let (throttle, cache) = {
+            let mut state = self.state.borrow_mut();
+            let throttle = state.throttles.entry(self.index).or_default().clone();
+            let cache = state.caches.entry(self.index).or_default().clone();
+            (throttle, cache)
         };
+        let controller = AbortController::new().map_err(Error::dom)?;
+        let client = provider::Client::new(controller.signal(), throttle, cache);
+        new_provider(&self.source, client, self.limits.max_pipelines)
+    }
+}
 
-        for project in projects {
-            let gitlab = gitlab.clone();
-            let document = document.clone();
-            let future = gitlab
-                .request_pipelines(project.id)
-                .and_then(move |pipelines| {
-                    if pipelines.len() > 0 {
-                        let project_id = project.id;
-                        dom::Dom::update_project(
-                            &document,
-                            project_id,
-                            &project.name,
-                            &project.group,
-                            &pipelines,
-                        );
-
-                        let mut max = 5;
-                        for pipeline in &pipelines {
-                            if max < 1 {
-                                break;
-                            }
-                            max -= 1;
-
-                            dom::Dom::update_pipeline(&document, project_id, &pipeline);
-
-                            let pipeline_id = pipeline.id;
-                            let document = document.clone();
-                            let gitlab = gitlab.clone();
-                            let future = gitlab
-                                .request_pipeline_detail(project_id, pipeline_id)
-                                .and_then(move |pipeline_detail| {
-                                    dom::Dom::update_pipeline_detail(
-                                        &document,
-                                        project_id,
-                                        &pipeline_detail,
-                                    );
-                                    let future = gitlab
-                                        .request_jobs(project_id, pipeline_id)
-                                        .and_then(move |jobs| {
-                                            dom::Dom::update_jobs(
-                                                &document,
-                                                project_id,
-                                                pipeline_id,
-                                                &jobs,
-                                            );
-                                            future::ok(JsValue::NULL)
-                                        });
-                                    let _ = future_to_promise(future);
-                                    future::ok(JsValue::NULL)
-                                });
-                            let _ = future_to_promise(future);
-                        }
-                    }
-                    future::ok(JsValue::NULL)
-                });
-            let _ = future_to_promise(future);
+fn document() -> Result<web_sys::Document, Error> {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| Error::Dom("should have a document on window".into()))
+}
+
+/// Confirm the action of `button`, show its expected outcome and send it.
+/// On success the source is refreshed, on failure the previous state is restored
+/// and the error shown in the source's status line.
+fn start_action(button: &Element) -> Result<(), Error> {
+    let kind = button
+        .get_attribute("data-action")
+        .and_then(|name| dom::ActionKind::from_name(&name))
+        .ok_or_else(|| Error::Dom("button without action".into()))?;
+    let target = button
+        .get_attribute("data-target")
+        .ok_or_else(|| Error::Dom("button without target".into()))?;
+    let button_source = ButtonSource::of(button)?;
+
+    let window = web_sys::window().ok_or_else(|| Error::Dom("no global `window` exists".into()))?;
+    let title = button.get_attribute("title").unwrap_or_default();
+    if !window
+        .confirm_with_message(&format!("{}?", title))
+        .map_err(Error::dom)?
+    {
+        return Ok(());
+    }
+    let document = document()?;
+    let pending = dom::Dom::show_pending_action(&document, button, kind)?;
+
+    let provider = button_source.provider()?;
+    let ButtonSource {
+        state,
+        index,
+        source,
+        limits,
+        project_key,
+        ..
+    } = button_source;
+    let action = dom::Action { kind, target };
+    spawn_local(async move {
+        match provider.request_action(&action).await {
+            Ok(()) => {
+                // the retried pipeline is not finished anymore
+                state.borrow_mut().finished.remove(&project_key);
+                if let Err(error) = update(state, index, source, limits) {
+                    console::log_1(&JsValue::from(error.to_string()));
+                }
+            }
+            Err(error) => {
+                console::log_1(&JsValue::from(format!("{} failed: {}", title, error)));
+                pending.undo();
+                let mut state = state.borrow_mut();
+                let status = state.source_status(index, source.label());
+                status.last_error = Some(error);
+                if let Err(error) = dom::Dom::update_source_status(&document, index, status) {
+                    console::log_1(&JsValue::from(error.to_string()));
+                }
+            }
         }
-        future::ok(JsValue::NULL)
     });
+    Ok(())
+}
+
+/// Show or hide the log of the job of `button`, requesting it when first shown.
+fn toggle_log(button: &Element) -> Result<(), Error> {
+    let target = button
+        .get_attribute("data-target")
+        .ok_or_else(|| Error::Dom("button without target".into()))?;
+    let Some(panel) = dom::Dom::toggle_log(&document()?, button)? else {
+        return Ok(());
+    };
+    let button_source = ButtonSource::of(button)?;
+    let provider = button_source.provider()?;
+    let lines = button_source.log_lines;
+    spawn_local(async move {
+        let log = provider.request_log(&target, lines).await;
+        dom::Dom::update_log(&panel, log.as_deref());
+    });
+    Ok(())
+}
+
+/// One refresh cycle of a source, from requesting its projects until
+/// the jobs of all displayed pipelines are rendered.
+struct Refresh {
+    document: web_sys::Document,
+    state: AppState,
+    index: usize,
+    source: Arc<SourceConfig>,
+    provider: Rc<dyn CiProvider>,
+    signal: AbortSignal,
+    throttle: Rc<Throttle>,
+    cache: Rc<Cache>,
+    max_pipelines: usize,
+    /// Number of projects updated at once, each has at most one request in flight.
+    max_requests: usize,
+}
+
+impl Refresh {
+    /// Update all projects of the source. Fails if the projects could not be
+    /// requested, otherwise returns the first error of a single project.
+    async fn run(&self) -> Result<Option<Error>, Error> {
+        let projects = self.provider.request_projects().await?;
+        let projects = self.source.filter.apply(projects);
+        self.state
+            .borrow_mut()
+            .set_projects(self.index, projects.clone());
+        dom::Dom::remove_stale_projects(&self.document, self.index, &projects);
+
+        let errors: Vec<Error> = stream::iter(&projects)
+            .map(|project| async move {
+                let project_key = dom::Dom::project_key(self.index, &project.id);
+                let result = self
+                    .cache
+                    .scoped(&project_key, self.update_project(project))
+                    .await;
+                match &result {
+                    Err(error) if !self.signal.aborted() => {
+                        console::log_1(&JsValue::from(error.to_string()));
+                        // the other projects keep their responses for conditional requests
+                        self.cache.discard_scope(&project_key);
+                        dom::Dom::mark_stale(&self.document, self.index, Some(&project_key));
+                    }
+                    _ => (),
+                }
+                result
+            })
+            .buffer_unordered(self.max_requests)
+            .filter_map(|result| future::ready(result.err()))
+            .collect()
+            .await;
+        if !self.signal.aborted() {
+            self.cache.prune();
+        }
+        Ok(errors.into_iter().next())
+    }
+
+    async fn update_project(&self, project: &dom::Project) -> Result<(), Error> {
+        let (modified, mut pipelines) = match self.source.view {
+            View::Pipelines => {
+                let fetched = self
+                    .provider
+                    .request_pipelines(project, self.max_pipelines)
+                    .await?;
+                let pipelines = self.source.refs_for(project).apply(project, fetched.value);
+                (fetched.modified, pipelines)
+            }
+            View::MergeRequests => {
+                let fetched = self
+                    .provider
+                    .request_merge_requests(project, self.max_pipelines)
+                    .await?;
+                (fetched.modified, fetched.value)
+            }
+        };
+        pipelines.truncate(self.max_pipelines);
+        let project_key = dom::Dom::project_key(self.index, &project.id);
+        if pipelines.is_empty() {
+            dom::Dom::remove_project(&self.document, &project_key);
+            return Ok(());
+        }
+        // an unchanged list is only rendered if the project was removed meanwhile
+        let rendered = self.document.get_element_by_id(&project_key).is_some();
+
+        dom::Dom::update_project(
+            &self.document,
+            &project_key,
+            self.source.label(),
+            &project.name,
+            &project.group,
+            &pipelines,
+        )?;
+        let mut added = HashSet::new();
+        if modified || !rendered {
+            dom::Dom::remove_stale_pipelines(&self.document, &project_key, &pipelines);
+            for pipeline in &pipelines {
+                if dom::Dom::update_pipeline(&self.document, &project_key, pipeline)? {
+                    added.insert(pipeline.id);
+                }
+            }
+        }
+
+        self.state
+            .borrow_mut()
+            .finished
+            .entry(project_key.clone())
+            .or_default()
+            // a pipeline retried or still canceling changes its status under the same id
+            .retain(|id, finished| {
+                pipelines
+                    .iter()
+                    .any(|p| p.id == *id && p.status == finished.detail.status)
+            });
+
+        for pipeline in &pipelines {
+            if pipeline.status.is_finished()
+                && self.render_finished(&project_key, pipeline, &added)?
+            {
+                continue;
+            }
+
+            let pipeline_detail = self
+                .provider
+                .request_pipeline_detail(&project.id, pipeline.id)
+                .await?;
+            dom::Dom::update_pipeline_detail(&self.document, &project_key, &pipeline_detail)?;
 
-    let _ = future_to_promise(future);
+            let jobs = self.provider.request_jobs(&project.id, pipeline.id).await?;
+            if jobs.modified || added.contains(&pipeline.id) {
+                dom::Dom::update_jobs(&self.document, &project_key, pipeline.id, &jobs.value)?;
+            }
+
+            // a pipeline may finish before the downstream pipelines it triggered
+            let downstream_finished = jobs
+                .value
+                .iter()
+                .filter_map(|job| job.downstream.as_ref())
+                .all(|downstream| downstream.status.is_finished());
+            if pipeline_detail.status.is_finished() && downstream_finished {
+                self.state
+                    .borrow_mut()
+                    .finished
+                    .entry(project_key.clone())
+                    .or_default()
+                    .insert(
+                        pipeline.id,
+                        FinishedPipeline {
+                            detail: pipeline_detail,
+                            jobs: jobs.value,
+                        },
+                    );
+            }
+        }
+        Ok(())
+    }
+
+    /// Render a finished pipeline from the state if it was just added to the page.
+    /// Returns `false` if the pipeline is not known as finished yet.
+    fn render_finished(
+        &self,
+        project_key: &str,
+        pipeline: &dom::Pipeline,
+        added: &HashSet<i64>,
+    ) -> Result<bool, Error> {
+        let state = self.state.borrow();
+        let finished = match state
+            .finished
+            .get(project_key)
+            .and_then(|pipelines| pipelines.get(&pipeline.id))
+        {
+            Some(finished) => finished,
+            None => return Ok(false),
+        };
+        if added.contains(&pipeline.id) {
+            dom::Dom::update_pipeline_detail(&self.document, project_key, &finished.detail)?;
+            dom::Dom::update_jobs(&self.document, project_key, pipeline.id, &finished.jobs)?;
+        }
+        Ok(true)
+    }
+
+    /// Render the outcome of the cycle in the status area of the source.
+    fn report(&self, result: Result<Option<Error>, Error>) {
+        let error = match result {
+            Ok(error) => error,
+            Err(error) => {
+                console::log_1(&JsValue::from(error.to_string()));
+                dom::Dom::mark_stale(&self.document, self.index, None);
+                Some(error)
+            }
+        };
+
+        let mut state = self.state.borrow_mut();
+        state.cycles.remove(&self.index);
+        let status = state.source_status(self.index, self.source.label());
+        if error.is_none() {
+            status.last_success = Some(js_sys::Date::now());
+        }
+        status.last_error = error;
+        status.refreshing_since = None;
+        status.throttled_until = self.throttle.paused_until();
+        if let Err(error) = dom::Dom::update_source_status(&self.document, self.index, status) {
+            console::log_1(&JsValue::from(error.to_string()));
+        }
+    }
 }
@@ -0,0 +1,517 @@
+use crate::dom;
+use crate::error::{js_message, Error};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use futures::Future;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::pin::pin;
+use std::rc::Rc;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AbortSignal, Headers, Request, RequestInit, RequestMode, Response, Window};
+
+pub type ProviderFuture<T> = LocalBoxFuture<'static, Result<T, Error>>;
+
+/// A CI backend delivering projects, their pipelines and jobs,
+/// already mapped onto the `dom` types used for rendering.
+pub trait CiProvider {
+    fn request_projects(&self) -> ProviderFuture<Vec<dom::Project>>;
+
+    /// The latest pipelines of a project, at least `limit` of them matching its
+    /// ref filter if the provider filters by paging.
+    fn request_pipelines(
+        &self,
+        project: &dom::Project,
+        limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>>;
+
+    fn request_pipeline_detail(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<dom::PipelineDetail>;
+
+    fn request_jobs(
+        &self,
+        project_id: &str,
+        pipeline_id: i64,
+    ) -> ProviderFuture<Fetched<Vec<dom::Job>>>;
+
+    /// Head pipelines of the open merge requests of a project, most recently updated first.
+    fn request_merge_requests(
+        &self,
+        _project: &dom::Project,
+        _limit: usize,
+    ) -> ProviderFuture<Fetched<Vec<dom::Pipeline>>> {
+        let error = Error::Config("merge request view is not supported by this provider".into());
+        futures::future::ready(Err(error)).boxed_local()
+    }
+
+    /// Retry, cancel or play a job or pipeline.
+    fn request_action(&self, _action: &dom::Action) -> ProviderFuture<()> {
+        let error = Error::Config("actions are not supported by this provider".into());
+        futures::future::ready(Err(error)).boxed_local()
+    }
+
+    /// The last `lines` lines of a job's log, as plain text without escape codes.
+    fn request_log(&self, _target: &str, _lines: usize) -> ProviderFuture<Vec<String>> {
+        let error = Error::Config("job logs are not supported by this provider".into());
+        futures::future::ready(Err(error)).boxed_local()
+    }
+}
+
+/// A response which may be unchanged since the previous request of the same
+/// URL, in which case the cached value is returned.
+pub struct Fetched<T> {
+    pub value: T,
+    /// `false` if the server answered 304 Not Modified.
+    pub modified: bool,
+}
+
+impl<T> Fetched<T> {
+    /// A freshly received value.
+    pub fn new(value: T) -> Self {
+        Fetched {
+            value,
+            modified: true,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Fetched<U> {
+        Fetched {
+            value: f(self.value),
+            modified: self.modified,
+        }
+    }
+}
+
+/// Retries of a request failing with a server error (5xx).
+const MAX_RETRIES: i32 = 3;
+/// Delay before the first retry in milliseconds, doubled for each further retry.
+const RETRY_DELAY: f64 = 1000.0;
+/// Pause in milliseconds if the server is rate limiting without telling until when.
+const DEFAULT_PAUSE: f64 = 60_000.0;
+
+/// Sends the requests of a source: aborted with the refresh cycle,
+/// honoring the rate limit of the server.
+#[derive(Clone)]
+pub struct Client {
+    signal: AbortSignal,
+    throttle: Rc<Throttle>,
+    cache: Rc<Cache>,
+    /// Sent with every request, e.g. the credentials of the source.
+    headers: Vec<(&'static str, String)>,
+}
+
+impl Client {
+    /// Requests are aborted by `signal`, e.g. when a refresh cycle is replaced.
+    pub fn new(signal: AbortSignal, throttle: Rc<Throttle>, cache: Rc<Cache>) -> Self {
+        Client {
+            signal,
+            throttle,
+            cache,
+            headers: vec![],
+        }
+    }
+
+    /// Send the header with every request of this client, e.g. for authentication.
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Create a CORS GET request accepting JSON, headers can be added by the caller.
+    pub fn new_request(&self, url: &str) -> Result<Request, Error> {
+        self.new_request_with_body("GET", url, None)
+    }
+
+    /// Create a CORS POST request sending and accepting JSON.
+    pub fn new_post_request(&self, url: &str, body: &str) -> Result<Request, Error> {
+        let request = self.new_request_with_body("POST", url, Some(body))?;
+        set_header(&request, "Content-Type", "application/json")?;
+        Ok(request)
+    }
+
+    fn new_request_with_body(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&str>,
+    ) -> Result<Request, Error> {
+        let mut opts = RequestInit::new();
+        opts.method(method);
+        opts.mode(RequestMode::Cors);
+        opts.signal(Some(&self.signal));
+        if let Some(body) = body {
+            opts.body(Some(&JsValue::from_str(body)));
+        }
+
+        let request = Request::new_with_str_and_init(url, &opts)
+            .map_err(|_| Error::Config(format!("Invalid request URL {}", url)))?;
+        set_header(&request, "Accept", "application/json")?;
+        for (name, value) in &self.headers {
+            set_header(&request, name, value)?;
+        }
+        Ok(request)
+    }
+
+    /// GET `url` and deserialize the JSON response body.
+    pub fn prepare_request<T: DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> impl Future<Output = Result<T, Error>> {
+        let request = self.new_request(url);
+        let client = self.clone();
+        async move { client.fetch_json(request?).await }
+    }
+
+    /// Send the request and deserialize the JSON response body.
+    pub async fn fetch_json<T: DeserializeOwned>(&self, request: Request) -> Result<T, Error> {
+        self.fetch_json_with_headers(request)
+            .await
+            .map(|(value, _)| value)
+    }
+
+    /// Like `fetch_json`, additionally returning the response headers,
+    /// e.g. for pagination.
+    pub async fn fetch_json_with_headers<T: DeserializeOwned>(
+        &self,
+        request: Request,
+    ) -> Result<(T, Headers), Error> {
+        let response = self.fetch(&request).await?;
+        let headers = response.headers();
+        let value = decode_json(&request.url(), &response).await?;
+        Ok((value, headers))
+    }
+
+    /// Like `fetch_json_with_headers`, sending the validators of the previous
+    /// response of the same URL. If the server answers 304 Not Modified, the
+    /// cached value is returned without deserializing anything.
+    pub async fn fetch_json_cached<T: DeserializeOwned + Clone + 'static>(
+        &self,
+        request: Request,
+    ) -> Result<Fetched<(T, Headers)>, Error> {
+        let url = request.url();
+        if let Some((etag, last_modified)) = self.cache.validators(&url) {
+            if let Some(etag) = etag {
+                set_header(&request, "If-None-Match", &etag)?;
+            }
+            if let Some(last_modified) = last_modified {
+                set_header(&request, "If-Modified-Since", &last_modified)?;
+            }
+        }
+
+        let response = self.fetch(&request).await?;
+        if response.status() == 304 {
+            return self
+                .cache
+                .hit::<(T, Headers)>(&url)
+                .map(|value| Fetched {
+                    value,
+                    modified: false,
+                })
+                .ok_or_else(|| Error::decode(&url, "not modified, but not cached"));
+        }
+
+        let headers = response.headers();
+        let value: T = decode_json(&url, &response).await?;
+        self.cache
+            .store(&url, &headers, (value.clone(), headers.clone()));
+        Ok(Fetched::new((value, headers)))
+    }
+
+    /// Send the request and return the response body as text, e.g. a job log.
+    pub async fn fetch_text(&self, request: Request) -> Result<String, Error> {
+        let url = request.url();
+        let response = self.fetch(&request).await?;
+        let text = response
+            .text()
+            .map_err(|e| Error::decode(&url, js_message(&e)))?;
+        let text = JsFuture::from(text)
+            .await
+            .map_err(|e| Error::decode(&url, js_message(&e)))?;
+        text.as_string()
+            .ok_or_else(|| Error::decode(&url, "response body is not text"))
+    }
+
+    /// Send the request unless the source is paused, retrying server errors
+    /// of GET requests with exponential backoff and jitter.
+    async fn fetch(&self, request: &Request) -> Result<Response, Error> {
+        let url = request.url();
+        let window =
+            web_sys::window().ok_or_else(|| Error::Dom("no global `window` exists".into()))?;
+
+        // a POST, e.g. retrying a job, may have been processed despite the error
+        let idempotent = request.method() == "GET";
+        let mut retries = 0;
+        loop {
+            if self.throttle.paused_until().is_some() {
+                return Err(Error::RateLimit { url });
+            }
+            // a body can be read only once, every attempt sends a copy
+            let attempt = Request::clone(request).map_err(|e| Error::network(&url, e))?;
+            let response: Response = JsFuture::from(window.fetch_with_request(&attempt))
+                .await
+                .and_then(|value| value.dyn_into())
+                .map_err(|e| Error::network(&url, e))?;
+            self.throttle.update(&response);
+            if response.ok() || response.status() == 304 {
+                return Ok(response);
+            }
+
+            match response.status() {
+                500..=599 if idempotent && retries < MAX_RETRIES => {
+                    let jitter = 0.5 + js_sys::Math::random();
+                    sleep(&window, RETRY_DELAY * 2f64.powi(retries) * jitter).await?;
+                    retries += 1;
+                }
+                status => return Err(Error::from_status(&url, status)),
+            }
+        }
+    }
+}
+
+async fn decode_json<T: DeserializeOwned>(url: &str, response: &Response) -> Result<T, Error> {
+    let json = response
+        .json()
+        .map_err(|e| Error::decode(url, js_message(&e)))?;
+    let jsvalue = JsFuture::from(json)
+        .await
+        .map_err(|e| Error::decode(url, js_message(&e)))?;
+
+    #[allow(deprecated)]
+    let parsed = jsvalue.into_serde::<T>();
+    parsed.map_err(|e| Error::decode(url, e))
+}
+
+/// Responses of a source by URL for conditional requests, kept across refresh cycles.
+#[derive(Default)]
+pub struct Cache {
+    entries: RefCell<HashMap<String, CacheEntry>>,
+    /// URLs stored since the last `commit` or `discard`, with the scope storing them.
+    refreshed: RefCell<HashMap<String, Option<String>>>,
+    /// Scope of the future being polled, see `scoped`.
+    scope: RefCell<Option<String>>,
+    hits: Cell<u32>,
+    misses: Cell<u32>,
+}
+
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    value: Rc<dyn Any>,
+    /// Requested since the last `prune`.
+    used: bool,
+}
+
+/// Cache statistics of a source, for debugging.
+#[derive(Serialize, Default)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+    pub entries: usize,
+}
+
+impl Cache {
+    fn validators(&self, url: &str) -> Option<(Option<String>, Option<String>)> {
+        self.entries
+            .borrow()
+            .get(url)
+            .map(|entry| (entry.etag.clone(), entry.last_modified.clone()))
+    }
+
+    fn hit<T: Clone + 'static>(&self, url: &str) -> Option<T> {
+        let mut entries = self.entries.borrow_mut();
+        let entry = entries.get_mut(url)?;
+        let value = entry.value.downcast_ref::<T>()?.clone();
+        entry.used = true;
+        self.hits.set(self.hits.get() + 1);
+        Some(value)
+    }
+
+    /// Keep the value if the response has a validator to revalidate it with.
+    fn store<T: 'static>(&self, url: &str, headers: &Headers, value: T) {
+        self.misses.set(self.misses.get() + 1);
+        let etag = header(headers, "ETag");
+        let last_modified = header(headers, "Last-Modified");
+        let mut entries = self.entries.borrow_mut();
+        if etag.is_none() && last_modified.is_none() {
+            entries.remove(url);
+            return;
+        }
+        self.refreshed
+            .borrow_mut()
+            .insert(url.to_string(), self.scope.borrow().clone());
+        entries.insert(
+            url.to_string(),
+            CacheEntry {
+                etag,
+                last_modified,
+                value: Rc::new(value),
+                used: true,
+            },
+        );
+    }
+
+    /// Drop the entries not requested since the previous call,
+    /// e.g. of pipelines no longer displayed.
+    pub fn prune(&self) {
+        self.entries.borrow_mut().retain(|_, entry| {
+            let used = entry.used;
+            entry.used = false;
+            used
+        });
+    }
+
+    /// Run `future`, the responses it stores belong to `scope`, e.g. a project
+    /// whose responses are discarded on their own if it fails.
+    pub async fn scoped<F: Future>(&self, scope: &str, future: F) -> F::Output {
+        let mut future = pin!(future);
+        future::poll_fn(|cx| {
+            let outer = self.scope.replace(Some(scope.to_string()));
+            let poll = future.as_mut().poll(cx);
+            self.scope.replace(outer);
+            poll
+        })
+        .await
+    }
+
+    /// Keep the responses stored during a refresh cycle which rendered them.
+    pub fn commit(&self) {
+        self.refreshed.borrow_mut().clear();
+    }
+
+    /// Drop the responses stored during a failed or aborted refresh cycle. Revalidated
+    /// they would be unmodified and not rendered, although the cycle may not have
+    /// rendered them, e.g. new jobs whose bridges failed to load.
+    pub fn discard(&self) {
+        let mut entries = self.entries.borrow_mut();
+        for (url, _) in self.refreshed.borrow_mut().drain() {
+            entries.remove(&url);
+        }
+    }
+
+    /// Like `discard`, only the responses stored by `scope`, e.g. of a project
+    /// which failed while the other projects of the cycle were rendered.
+    pub fn discard_scope(&self, scope: &str) {
+        let mut entries = self.entries.borrow_mut();
+        self.refreshed.borrow_mut().retain(|url, stored_by| {
+            if stored_by.as_deref() != Some(scope) {
+                return true;
+            }
+            entries.remove(url);
+            false
+        });
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            entries: self.entries.borrow().len(),
+        }
+    }
+}
+
+/// Rate limit state of a source, kept across refresh cycles.
+#[derive(Default)]
+pub struct Throttle {
+    /// No requests are sent before this time, in milliseconds since the epoch.
+    paused_until: Cell<f64>,
+}
+
+impl Throttle {
+    /// End of the current pause, `None` if requests may be sent.
+    pub fn paused_until(&self) -> Option<f64> {
+        Some(self.paused_until.get()).filter(|until| *until > js_sys::Date::now())
+    }
+
+    /// Pause the source on 429 or when no requests are remaining, until the
+    /// time given by `Retry-After` or the `RateLimit-Reset` headers.
+    fn update(&self, response: &Response) {
+        let headers = response.headers();
+        let remaining = rate_limit_header(&headers, "Remaining");
+        if response.status() != 429 && remaining != Some(0.0) {
+            return;
+        }
+
+        let now = js_sys::Date::now();
+        let until = header(&headers, "Retry-After")
+            .and_then(|value| match value.trim().parse::<f64>() {
+                Ok(seconds) => Some(now + seconds * 1000.0),
+                // or an HTTP date
+                Err(_) => Some(js_sys::Date::parse(&value)).filter(|millis| !millis.is_nan()),
+            })
+            .or_else(|| rate_limit_header(&headers, "Reset").map(|seconds| seconds * 1000.0))
+            .filter(|until| *until > now)
+            .unwrap_or(now + DEFAULT_PAUSE);
+        self.paused_until.set(until.max(self.paused_until.get()));
+    }
+}
+
+fn header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get(name).ok().flatten()
+}
+
+/// Numeric `RateLimit-*` header as sent by GitLab, or `X-RateLimit-*` as sent by GitHub.
+fn rate_limit_header(headers: &Headers, name: &str) -> Option<f64> {
+    header(headers, &format!("RateLimit-{}", name))
+        .or_else(|| header(headers, &format!("X-RateLimit-{}", name)))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// URL of the next page from a `Link` header (`<url>; rel="next"`), as sent by GitLab and GitHub.
+pub fn next_link(headers: &Headers) -> Option<String> {
+    let link = header(headers, "Link")?;
+    link.split(',').find_map(|part| {
+        let (target, rel) = part.split_once(';')?;
+        if rel.trim() != r#"rel="next""# {
+            return None;
+        }
+        Some(
+            target
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string(),
+        )
+    })
+}
+
+async fn sleep(window: &Window, millis: f64) -> Result<(), Error> {
+    let mut result = Ok(0);
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        result =
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis as i32);
+    });
+    result.map_err(Error::dom)?;
+    JsFuture::from(promise)
+        .await
+        .map(|_| ())
+        .map_err(Error::dom)
+}
+
+pub fn set_header(request: &Request, name: &str, value: &str) -> Result<(), Error> {
+    request
+        .headers()
+        .set(name, value)
+        .map_err(|_| Error::Config(format!("Invalid value for header {}", name)))
+}
+
+/// Seconds between two ISO 8601 timestamps, 0 if either is missing or invalid.
+pub fn seconds_between(start: Option<&str>, end: Option<&str>) -> i32 {
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            let millis = js_sys::Date::parse(end) - js_sys::Date::parse(start);
+            if millis.is_nan() || millis < 0.0 {
+                0
+            } else {
+                (millis / 1000.0) as i32
+            }
+        }
+        _ => 0,
+    }
+}
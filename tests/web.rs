@@ -3,11 +3,398 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use ci_dashboard::dom::{
+    Action, ActionKind, Dom, Downstream, Job, MergeRequest, Pipeline, PipelineDetail, SourceStatus,
+    Status, TestSummary,
+};
 use wasm_bindgen_test::*;
+use web_sys::Document;
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+const HOSTILE: &str = r#"<img src=x onerror="window.pwned=true">"#;
+
+fn document() -> Document {
+    let document = web_sys::window().unwrap().document().unwrap();
+    if document.get_element_by_id("Content").is_none() {
+        let content = document.create_element("div").unwrap();
+        content.set_id("Content");
+        document.body().unwrap().append_child(&content).unwrap();
+    }
+    document
+}
+
+fn pipeline(id: i64) -> Pipeline {
+    Pipeline {
+        id,
+        status: Status::FAILED,
+        r#ref: HOSTILE.to_string(),
+        merge_request: None,
+    }
+}
+
+/// Render a project card holding `pipeline`, returns the key of the project.
+fn render_pipeline(document: &Document, name: &str, pipeline: &Pipeline) -> String {
+    let project_key = Dom::project_key(0, name);
+    let pipelines = std::slice::from_ref(pipeline);
+    Dom::update_project(document, &project_key, "", "p", "g", pipelines).unwrap();
+    Dom::update_pipeline(document, &project_key, pipeline).unwrap();
+    project_key
+}
+
 #[wasm_bindgen_test]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn hostile_project_and_ref_render_literally() {
+    let document = document();
+    let project_key = Dom::project_key(0, "hostile_project");
+    Dom::update_project(
+        &document,
+        &project_key,
+        HOSTILE,
+        HOSTILE,
+        HOSTILE,
+        &[pipeline(1)],
+    )
+    .unwrap();
+    Dom::update_pipeline(&document, &project_key, &pipeline(1)).unwrap();
+    Dom::update_pipeline_detail(
+        &document,
+        &project_key,
+        &PipelineDetail {
+            id: 1,
+            status: Status::FAILED,
+            r#ref: HOSTILE.to_string(),
+            duration: 61,
+            actions: vec![],
+            tests: None,
+        },
+    )
+    .unwrap();
+
+    let project = document.get_element_by_id(&project_key).unwrap();
+    assert!(project.query_selector("img").unwrap().is_none());
+    assert!(project.text_content().unwrap().contains(HOSTILE));
+
+    let label = document
+        .get_element_by_id(&format!("{}_pl1_label", project_key))
+        .unwrap();
+    assert_eq!(label.text_content().unwrap(), format!("#1 / {}", HOSTILE));
+}
+
+#[wasm_bindgen_test]
+fn hostile_job_renders_literally() {
+    let document = document();
+    let project_key = render_pipeline(&document, "hostile_job", &pipeline(2));
+    Dom::update_jobs(
+        &document,
+        &project_key,
+        2,
+        &[Job {
+            name: HOSTILE.to_string(),
+            stage: HOSTILE.to_string(),
+            status: Status::FAILED,
+            link: "javascript:window.pwned=true".to_string(),
+            downstream: None,
+            log: None,
+            actions: vec![],
+        }],
+    )
+    .unwrap();
+
+    let pipeline = document
+        .get_element_by_id(&format!("{}_pl2", project_key))
+        .unwrap();
+    assert!(pipeline.query_selector("img").unwrap().is_none());
+
+    let link = pipeline.query_selector(".job a").unwrap().unwrap();
+    assert_eq!(link.text_content().unwrap(), HOSTILE);
+    assert!(link.get_attribute("href").is_none());
+
+    let stage = pipeline.query_selector(".stage-name").unwrap().unwrap();
+    assert_eq!(stage.text_content().unwrap(), HOSTILE);
+}
+
+fn job(name: &str, stage: &str) -> Job {
+    Job {
+        name: name.to_string(),
+        stage: stage.to_string(),
+        status: Status::SUCCESS,
+        link: "https://example.com".to_string(),
+        downstream: None,
+        log: None,
+        actions: vec![],
+    }
+}
+
+#[wasm_bindgen_test]
+fn jobs_grouped_by_stage_in_order() {
+    let document = document();
+    let project_key = render_pipeline(&document, "stages", &pipeline(4));
+    let jobs = [
+        job("compile", "build"),
+        job("unit", "test"),
+        job("lint", "build"),
+        job("deploy", "deploy"),
+    ];
+    Dom::update_jobs(&document, &project_key, 4, &jobs).unwrap();
+    // updating again must not duplicate anything
+    Dom::update_jobs(&document, &project_key, 4, &jobs).unwrap();
+
+    let stages = document
+        .get_element_by_id(&format!("{}_pl4_stages", project_key))
+        .unwrap()
+        .children();
+    assert_eq!(stages.length(), 3);
+    let names: Vec<String> = (0..stages.length())
+        .map(|i| stages.item(i).unwrap().text_content().unwrap())
+        .collect();
+    assert_eq!(names, ["buildcompilelint", "testunit", "deploydeploy"]);
+}
+
+#[wasm_bindgen_test]
+fn source_status_shows_error_and_marks_stale() {
+    let document = document();
+    let project_key = Dom::project_key(7, "stale");
+    Dom::update_project(
+        &document,
+        &project_key,
+        "label",
+        "name",
+        "group",
+        &[pipeline(3)],
+    )
+    .unwrap();
+
+    let mut status = SourceStatus::new(HOSTILE);
+    Dom::update_source_status(&document, 7, &status).unwrap();
+    let line = document.get_element_by_id("status_s7").unwrap();
+    assert!(line.class_name().contains("source-pending"));
+    assert!(line.query_selector("img").unwrap().is_none());
+
+    status.last_success = Some(js_sys::Date::now());
+    Dom::update_source_status(&document, 7, &status).unwrap();
+    assert!(line.class_name().contains("source-ok"));
+    assert!(line.query_selector(".error").unwrap().is_none());
+    assert!(line.query_selector(".throttled").unwrap().is_none());
+
+    status.throttled_until = Some(js_sys::Date::now() + 60_000.0);
+    Dom::update_source_status(&document, 7, &status).unwrap();
+    let throttled = line.query_selector(".throttled").unwrap().unwrap();
+    assert!(throttled
+        .text_content()
+        .unwrap()
+        .starts_with("paused until"));
+
+    Dom::mark_stale(&document, 7, None);
+    let project = document.get_element_by_id(&project_key).unwrap();
+    assert!(project
+        .class_name()
+        .split_whitespace()
+        .any(|c| c == "stale"));
+
+    Dom::update_project(
+        &document,
+        &project_key,
+        "label",
+        "name",
+        "group",
+        &[pipeline(3)],
+    )
+    .unwrap();
+    assert!(!project.class_name().contains("stale"));
+}
+
+#[wasm_bindgen_test]
+fn downstream_pipeline_nested_under_bridge() {
+    let document = document();
+    let project_key = render_pipeline(&document, "downstream", &pipeline(5));
+    let mut bridge = job("trigger", "deploy");
+    bridge.downstream = Some(Downstream {
+        id: 6,
+        status: Status::RUNNING,
+        r#ref: "main".to_string(),
+        link: "https://example.com/pipelines/6".to_string(),
+        jobs: vec![job("child", "build")],
+    });
+    Dom::update_jobs(
+        &document,
+        &project_key,
+        5,
+        &[job("compile", "build"), bridge],
+    )
+    .unwrap();
+
+    let bridge_key = format!("{}_pl5_trigger", project_key);
+    let bridge = document.get_element_by_id(&bridge_key).unwrap();
+    let downstream = bridge.next_element_sibling().unwrap();
+    assert!(downstream.class_name().contains("downstream"));
+    assert!(downstream.class_name().contains("bg-running"));
+    assert!(document
+        .get_element_by_id(&format!("{}_pl6_child", bridge_key))
+        .is_some());
+}
+
+#[wasm_bindgen_test]
+fn merge_request_rendered_above_pipeline() {
+    let document = document();
+    let mut pipeline = pipeline(1);
+    pipeline.merge_request = Some(MergeRequest {
+        iid: 7,
+        title: HOSTILE.to_string(),
+        author: HOSTILE.to_string(),
+        target_branch: "main".to_string(),
+        draft: true,
+        link: "javascript:alert(1)".to_string(),
+    });
+    let project_key = render_pipeline(&document, "merge_request", &pipeline);
+    // updating an existing pipeline keeps a single header
+    Dom::update_pipeline(&document, &project_key, &pipeline).unwrap();
+
+    let container = document
+        .get_element_by_id(&format!("{}_pl1", project_key))
+        .unwrap();
+    let header = container.first_element_child().unwrap();
+    assert_eq!(header.class_name(), "merge-request");
+    assert_eq!(
+        container
+            .get_elements_by_class_name("merge-request")
+            .length(),
+        1
+    );
+    assert!(header.query_selector("img").unwrap().is_none());
+    assert!(header.query_selector(".draft").unwrap().is_some());
+
+    let title = header.query_selector(".title").unwrap().unwrap();
+    assert_eq!(title.text_content().unwrap(), format!("!7 {}", HOSTILE));
+    assert!(title.get_attribute("href").is_none());
+    assert!(header
+        .text_content()
+        .unwrap()
+        .contains(&format!("{} \u{2192} main", HOSTILE)));
+}
+
+#[wasm_bindgen_test]
+fn pending_action_shown_and_undone() {
+    let document = document();
+    let project_key = render_pipeline(&document, "actions", &pipeline(1));
+    let mut failed = job("build", "build");
+    failed.status = Status::FAILED;
+    failed.actions = vec![Action {
+        kind: ActionKind::Retry,
+        target: "projects/1/jobs/7".to_string(),
+    }];
+    Dom::update_jobs(&document, &project_key, 1, &[failed]).unwrap();
+
+    let job = document
+        .get_element_by_id(&format!("{}_pl1_build", project_key))
+        .unwrap();
+    let button = job.query_selector("button.action").unwrap().unwrap();
+    assert_eq!(button.get_attribute("data-action").unwrap(), "retry");
+    assert_eq!(
+        button.get_attribute("data-target").unwrap(),
+        "projects/1/jobs/7"
+    );
+    assert_eq!(button.get_attribute("title").unwrap(), "Retry build");
+
+    let pending = Dom::show_pending_action(&document, &button, ActionKind::Retry).unwrap();
+    assert_eq!(job.class_name(), "job job-skipped");
+    assert!(button.has_attribute("disabled"));
+
+    pending.undo();
+    assert_eq!(job.class_name(), "job job-fail");
+    assert_eq!(
+        job.first_element_child().unwrap().class_name(),
+        "fas fa-times-circle"
+    );
+    assert!(!button.has_attribute("disabled"));
+}
+
+#[wasm_bindgen_test]
+fn log_panel_toggled_and_rendered_literally() {
+    let document = document();
+    let project_key = render_pipeline(&document, "log", &pipeline(1));
+    let mut failed = job("test", "test");
+    failed.status = Status::FAILED;
+    failed.log = Some("projects/1/jobs/8".to_string());
+    let jobs = [failed.clone(), job("deploy", "deploy")];
+    Dom::update_jobs(&document, &project_key, 1, &[failed]).unwrap();
+
+    let job = document
+        .get_element_by_id(&format!("{}_pl1_test", project_key))
+        .unwrap();
+    let button = job.query_selector("button.log-toggle").unwrap().unwrap();
+    assert_eq!(
+        button.get_attribute("data-target").unwrap(),
+        "projects/1/jobs/8"
+    );
+
+    let panel = Dom::toggle_log(&document, &button).unwrap().unwrap();
+    let lines = vec!["$ make test".to_string(), HOSTILE.to_string()];
+    Dom::update_log(&panel, Ok(&lines));
+    assert!(panel.query_selector("img").unwrap().is_none());
+    assert_eq!(
+        panel.text_content().unwrap(),
+        format!("$ make test\n{}", HOSTILE)
+    );
+
+    // the log is requested once, later clicks only hide and show it
+    assert!(Dom::toggle_log(&document, &button).unwrap().is_none());
+    assert!(panel.has_attribute("hidden"));
+    assert!(Dom::toggle_log(&document, &button).unwrap().is_none());
+    assert!(!panel.has_attribute("hidden"));
+
+    // an open log stays open when the jobs of a running pipeline change
+    Dom::update_jobs(&document, &project_key, 1, &jobs).unwrap();
+    let job = document
+        .get_element_by_id(&format!("{}_pl1_test", project_key))
+        .unwrap();
+    let log = job.query_selector("pre.log").unwrap().unwrap();
+    assert_eq!(
+        log.text_content().unwrap(),
+        format!("$ make test\n{}", HOSTILE)
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_summary_lists_failing_tests() {
+    let document = document();
+    let project_key = render_pipeline(&document, "tests", &pipeline(1));
+    Dom::update_pipeline_detail(
+        &document,
+        &project_key,
+        &PipelineDetail {
+            id: 1,
+            status: Status::FAILED,
+            r#ref: "main".to_string(),
+            duration: 0,
+            actions: vec![],
+            tests: Some(TestSummary {
+                total: 20,
+                failed: 2,
+                skipped: 3,
+                error: 1,
+                failing: vec![HOSTILE.to_string()],
+            }),
+        },
+    )
+    .unwrap();
+
+    let tests = document
+        .get_element_by_id(&format!("{}_pl1_tests", project_key))
+        .unwrap();
+    assert_eq!(tests.class_name(), "tests tests-failed");
+    assert!(tests.query_selector("img").unwrap().is_none());
+    let badge = tests.query_selector(".badge").unwrap().unwrap();
+    assert_eq!(
+        badge.text_content().unwrap(),
+        "14/20 passed, 2 failed, 1 errors, 3 skipped"
+    );
+    let items = tests.query_selector("ul").unwrap().unwrap().children();
+    assert_eq!(items.length(), 2);
+    assert_eq!(items.item(0).unwrap().text_content().unwrap(), HOSTILE);
+    assert_eq!(items.item(1).unwrap().text_content().unwrap(), "and 2 more");
+}
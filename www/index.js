@@ -1,6 +1,9 @@
 loadJSON(function (data) {
     const rust = import("ci-dashboard");
-    rust.then(m => m.run(data));
+    rust.then(m => {
+        m.run(data);
+        window.ciDashboard = m;
+    });
 })
 
 function loadJSON(callback) {